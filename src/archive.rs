@@ -0,0 +1,97 @@
+use crate::sanitize;
+use crate::sanitize::SanitizeError;
+use crate::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use zip::ZipArchive;
+
+/// A file or folder in the package.
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    /// Position in the zip's central directory.
+    pub index: usize,
+    /// Path inside the package, e.g. Skins\Suite\Main.ini.
+    pub path: PathBuf,
+    /// Whether the entry is a folder.
+    pub is_dir: bool,
+    /// Uncompressed size in bytes.
+    pub size: u64,
+}
+
+/// The package opened in place, entries are read straight from the zip
+/// into their destination instead of going through a temp folder.
+#[derive(Debug)]
+pub struct PackageArchive {
+    archive: ZipArchive<fs::File>,
+    /// Every entry with a safe name, in zip order.
+    pub entries: Vec<ArchiveEntry>,
+}
+
+impl PackageArchive {
+    /// Opens the zip and refuses it if any entry name is unsafe to extract.
+    pub fn open(path: &Path) -> Result<PackageArchive, Error> {
+        let file = fs::File::open(path)?;
+        let mut archive = ZipArchive::new(file)?;
+
+        let rejected = sanitize::check_archive(&mut archive)?;
+        if !rejected.is_empty() {
+            return Err(Error::UnsafeEntries(SanitizeError { rejected }));
+        }
+
+        let mut entries = vec![];
+        for index in 0..archive.len() {
+            let file = archive.by_index(index)?;
+            let path = match file.enclosed_name() {
+                Some(path) => path.to_owned(),
+                None => continue,
+            };
+            entries.push(ArchiveEntry {
+                index,
+                path,
+                is_dir: file.is_dir(),
+                size: file.size(),
+            });
+        }
+
+        Ok(PackageArchive { archive, entries })
+    }
+
+    /// The entry at `path` inside the package.
+    pub fn find(&self, path: &Path) -> Option<ArchiveEntry> {
+        self.entries.iter().find(|e| e.path == path).cloned()
+    }
+
+    /// Reads a whole entry into memory.
+    pub fn read(&mut self, entry: &ArchiveEntry) -> io::Result<Vec<u8>> {
+        let mut file = self.archive.by_index(entry.index)?;
+        let mut data = Vec::with_capacity(entry.size as usize);
+        io::copy(&mut file, &mut data)?;
+        Ok(data)
+    }
+
+    /// Streams a single entry to `dest`, creating parent folders as needed.
+    pub fn extract(&mut self, entry: &ArchiveEntry, dest: &Path) -> io::Result<()> {
+        extract_entry(&mut self.archive, entry, dest)
+    }
+}
+
+fn extract_entry(
+    archive: &mut ZipArchive<fs::File>,
+    entry: &ArchiveEntry,
+    dest: &Path,
+) -> io::Result<()> {
+    if entry.is_dir {
+        return fs::create_dir_all(dest);
+    }
+
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = archive.by_index(entry.index)?;
+    let mut outfile = fs::File::create(dest)?;
+    io::copy(&mut file, &mut outfile)?;
+    Ok(())
+}
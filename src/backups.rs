@@ -0,0 +1,392 @@
+// generations of backups kept in Skins\@Backup, one folder or zip per install
+// named after when it was made and the version it holds, e.g.
+// @Backup\Suite\20240131-184502-1.2.0, listed in @Backup\Backups.json so old
+// generations can be pruned and listed without walking every folder
+
+use crate::ini::IniFile;
+use crate::plan;
+use crate::storage;
+use crate::Error;
+use crate::RainmeterSettings;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+use zip::ZipWriter;
+
+/// The format of Backups.json this installer writes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// How a backup generation is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupFormat {
+    /// A plain copy of the folder.
+    #[default]
+    Folder,
+    /// A compressed zip that also holds the layouts and plugins the install
+    /// replaces and the skin's Rainmeter.ini sections. Rainmeter doesn't see a
+    /// zip as a skin, so it never shows up in the skin tree.
+    Zip,
+}
+
+impl FromStr for BackupFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "folder" => Ok(BackupFormat::Folder),
+            "zip" => Ok(BackupFormat::Zip),
+            _ => Err(format!("\"{}\" is not one of folder, zip", s)),
+        }
+    }
+}
+
+impl fmt::Display for BackupFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupFormat::Folder => write!(f, "folder"),
+            BackupFormat::Zip => write!(f, "zip"),
+        }
+    }
+}
+
+/// How many generations a skin or addon keeps, a new backup always survives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupRetention {
+    /// Generations kept, None keeps all.
+    pub count: Option<usize>,
+    /// Generations older than this are deleted.
+    pub max_age_days: Option<u64>,
+}
+
+impl Default for BackupRetention {
+    fn default() -> BackupRetention {
+        BackupRetention {
+            count: Some(5),
+            max_age_days: None,
+        }
+    }
+}
+
+/// One backup generation listed in Backups.json.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupEntry {
+    /// Skins or Addons.
+    pub component: String,
+    /// The skin root or addon backed up.
+    pub name: String,
+    /// Relative to the @Backup folder.
+    pub path: PathBuf,
+    /// Seconds since 1970-01-01 UTC.
+    pub created: u64,
+    /// The installed version that was backed up.
+    pub version: Option<String>,
+    /// Bytes on disk.
+    pub size: u64,
+}
+
+/// The contents of `Skins\@Backup\Backups.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupIndex {
+    /// Refused when newer than [`FORMAT_VERSION`].
+    pub format: u32,
+    /// Every generation, in the order they were made.
+    pub backups: Vec<BackupEntry>,
+}
+
+impl Default for BackupIndex {
+    fn default() -> BackupIndex {
+        BackupIndex {
+            format: FORMAT_VERSION,
+            backups: vec![],
+        }
+    }
+}
+
+impl BackupIndex {
+    /// `Skins\@Backup`, where every generation is kept.
+    pub fn folder(settings: &RainmeterSettings) -> PathBuf {
+        Path::new(&settings.skins_path).join("@Backup")
+    }
+
+    /// Where the index is kept.
+    pub fn path(settings: &RainmeterSettings) -> PathBuf {
+        BackupIndex::folder(settings).join("Backups.json")
+    }
+
+    /// Reads the index, an empty one if there is none. Backups whose folder was
+    /// deleted by hand are dropped.
+    pub fn load(settings: &RainmeterSettings) -> Result<BackupIndex, Error> {
+        let path = BackupIndex::path(settings);
+        if !path.is_file() {
+            return Ok(BackupIndex::default());
+        }
+        let mut index = storage::parse_json(
+            &fs::read_to_string(path)?,
+            FORMAT_VERSION,
+            |index: &BackupIndex| index.format,
+        )
+        .map_err(Error::Backups)?;
+        let folder = BackupIndex::folder(settings);
+        index
+            .backups
+            .retain(|backup| folder.join(&backup.path).exists());
+        Ok(index)
+    }
+
+    /// Writes the index, replacing the previous one only once it is complete.
+    pub fn save(&self, settings: &RainmeterSettings) -> Result<(), Error> {
+        storage::save_json(&BackupIndex::path(settings), self)?;
+        Ok(())
+    }
+
+    /// Bytes used by every generation together.
+    pub fn total_size(&self) -> u64 {
+        self.backups.iter().map(|backup| backup.size).sum()
+    }
+
+    /// The generations of one skin or addon to delete once a new one is made at `now`.
+    pub fn expired(
+        &self,
+        component: &str,
+        name: &str,
+        retention: BackupRetention,
+        now: u64,
+    ) -> Vec<BackupEntry> {
+        let mut generations: Vec<&BackupEntry> = self
+            .backups
+            .iter()
+            .filter(|b| b.component == component && b.name.eq_ignore_ascii_case(name))
+            .collect();
+        generations.sort_by_key(|backup| std::cmp::Reverse(backup.created));
+
+        // the new backup takes one of the places
+        let keep = retention.count.map(|count| count.max(1) - 1);
+        generations
+            .into_iter()
+            .enumerate()
+            .filter(|(i, backup)| {
+                keep.is_some_and(|keep| *i >= keep)
+                    || retention.max_age_days.is_some_and(|days| {
+                        // an age too large to count in seconds expires nothing
+                        backup.created.saturating_add(days.saturating_mul(86400)) < now
+                    })
+            })
+            .map(|(_, backup)| backup.clone())
+            .collect()
+    }
+}
+
+/// A new generation folder or zip below `dest`, never one that already exists.
+pub fn generation_path(
+    dest: &Path,
+    created: u64,
+    version: Option<&str>,
+    format: BackupFormat,
+) -> PathBuf {
+    let mut name = timestamp(created);
+    if let Some(version) = version {
+        let version: String = version
+            .chars()
+            .map(|c| match c {
+                '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c => c,
+            })
+            .collect();
+        name = format!("{}-{}", name, version.trim());
+    }
+    let extension = match format {
+        BackupFormat::Folder => "",
+        BackupFormat::Zip => ".zip",
+    };
+    let mut path = dest.join(format!("{}{}", name, extension));
+    let mut n = 2;
+    while path.exists() {
+        path = dest.join(format!("{}-{}{}", name, n, extension));
+        n += 1;
+    }
+    path
+}
+
+/// Writes `files`, source and name in the zip, one at a time so a large suite
+/// is never held in memory. `rainmeter_ini` is stored as Rainmeter.ini. The zip
+/// only gets its name once it is complete.
+pub fn write_zip(
+    dest: &Path,
+    files: &[(PathBuf, PathBuf)],
+    rainmeter_ini: Option<&IniFile>,
+) -> io::Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = dest.with_extension("zip.tmp");
+    let mut zip = ZipWriter::new(File::create(&temp)?);
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+
+    for (source, name) in files {
+        zip.start_file(zip_name(name), options)?;
+        io::copy(&mut File::open(source)?, &mut zip)?;
+    }
+    if let Some(rainmeter_ini) = rainmeter_ini {
+        zip.start_file("Rainmeter.ini", options)?;
+        io::Write::write_all(&mut zip, &rainmeter_ini.to_bytes())?;
+    }
+    zip.finish()?;
+    fs::rename(&temp, dest)
+}
+
+// zips always separate folders with /
+fn zip_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The sections of Rainmeter.ini holding the configs of a skin root, `[Root]` and `[Root\...]`.
+pub fn config_sections(rainmeter_ini: &IniFile, root: &str) -> Vec<String> {
+    let prefix = format!("{}\\", root.to_lowercase());
+    rainmeter_ini
+        .sections()
+        .into_iter()
+        .filter(|section| {
+            let section = section.to_lowercase();
+            section == root.to_lowercase() || section.starts_with(&prefix)
+        })
+        .collect()
+}
+
+/// Bytes of a file, or of every file below a folder.
+pub fn folder_size(path: &Path) -> u64 {
+    if path.is_file() {
+        return fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    }
+    plan::list_files(path)
+        .iter()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// YYYYMMDD-HHMMSS in UTC.
+pub fn timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // days since 1970-01-01 to a civil date, from Howard Hinnant's date algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, created: u64) -> BackupEntry {
+        BackupEntry {
+            component: "Skins".to_owned(),
+            name: name.to_owned(),
+            path: PathBuf::from(name).join(timestamp(created)),
+            created,
+            version: None,
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn names_zip_generations() {
+        let dest = Path::new("missing-backup-dir");
+        assert_eq!(
+            generation_path(dest, 0, Some("1.2/beta"), BackupFormat::Zip),
+            dest.join("19700101-000000-1.2_beta.zip")
+        );
+        assert_eq!(
+            zip_name(&["Skins", "Suite", "Clock.ini"].iter().collect::<PathBuf>()),
+            "Skins/Suite/Clock.ini"
+        );
+        assert_eq!("ZIP".parse::<BackupFormat>(), Ok(BackupFormat::Zip));
+    }
+
+    #[test]
+    fn finds_config_sections() {
+        let ini = IniFile::parse(
+            "[Rainmeter]\n\n[Suite\\Clock]\nActive=1\n\n[suite]\nActive=0\n\n[SuiteOther]\nActive=1\n",
+            crate::ini::Encoding::Utf8,
+        );
+        assert_eq!(
+            config_sections(&ini, "Suite"),
+            vec!["Suite\\Clock", "suite"]
+        );
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(timestamp(0), "19700101-000000");
+        assert_eq!(timestamp(951782400), "20000229-000000");
+        assert_eq!(timestamp(1706726702), "20240131-184502");
+    }
+
+    #[test]
+    fn expires_old_generations() {
+        let day = 86400;
+        let index = BackupIndex {
+            format: FORMAT_VERSION,
+            backups: vec![
+                entry("Suite", day),
+                entry("Suite", 3 * day),
+                entry("Suite", 2 * day),
+                entry("Other", day),
+            ],
+        };
+
+        let retention = BackupRetention {
+            count: Some(2),
+            max_age_days: None,
+        };
+        let expired = index.expired("Skins", "suite", retention, 4 * day);
+        let created: Vec<u64> = expired.iter().map(|b| b.created).collect();
+        assert_eq!(created, vec![2 * day, day]);
+
+        let retention = BackupRetention {
+            count: None,
+            max_age_days: Some(2),
+        };
+        let expired = index.expired("Skins", "Suite", retention, 4 * day);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].created, day);
+
+        let retention = BackupRetention {
+            count: None,
+            max_age_days: Some(u64::MAX),
+        };
+        assert!(index
+            .expired("Skins", "Suite", retention, u64::MAX)
+            .is_empty());
+    }
+}
@@ -0,0 +1,279 @@
+// skin files the user edited since the previous install wrote them. the
+// receipt of that install has their hashes, a file that no longer matches
+// would lose the user's change when the package overwrites it
+
+use crate::plan::PlannedFile;
+use crate::receipts;
+use crate::receipts::Receipt;
+use crate::receipts::ReceiptFile;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// What happens to a skin file the user changed, VariableFiles are carried over instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModifiedFilePolicy {
+    /// The package's file is installed, the user's is kept next to it as `<name>.user.ini`.
+    #[default]
+    SideCopy,
+    /// The user's file stays in place, the package's is not installed.
+    Keep,
+    /// The package's file replaces the user's.
+    Overwrite,
+}
+
+impl FromStr for ModifiedFilePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "side-copy" => Ok(ModifiedFilePolicy::SideCopy),
+            "keep" => Ok(ModifiedFilePolicy::Keep),
+            "overwrite" => Ok(ModifiedFilePolicy::Overwrite),
+            _ => Err(format!(
+                "\"{}\" is not one of side-copy, keep, overwrite",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ModifiedFilePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModifiedFilePolicy::SideCopy => write!(f, "side-copy"),
+            ModifiedFilePolicy::Keep => write!(f, "keep"),
+            ModifiedFilePolicy::Overwrite => write!(f, "overwrite"),
+        }
+    }
+}
+
+/// A skin file the user changed that the package would overwrite.
+#[derive(Debug, Clone)]
+pub struct FileConflict {
+    /// The user's file.
+    pub path: PathBuf,
+    /// What the install does with it.
+    pub policy: ModifiedFilePolicy,
+    /// Where the user's file is kept with side-copy.
+    pub side_copy: Option<PathBuf>,
+    /// What the previous install wrote, still recorded when the user's file is kept.
+    pub recorded: ReceiptFile,
+}
+
+impl fmt::Display for FileConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.policy, &self.side_copy) {
+            (ModifiedFilePolicy::SideCopy, Some(side_copy)) => write!(
+                f,
+                "{} changed by the user, kept as {}",
+                self.path.display(),
+                side_copy.display()
+            ),
+            (ModifiedFilePolicy::Keep, _) => write!(
+                f,
+                "{} changed by the user, kept in place, the package's version was not installed",
+                self.path.display()
+            ),
+            _ => write!(
+                f,
+                "{} changed by the user, overwritten",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+// the planned skin files that exist, were written by the previous install and
+// were changed since. `is_variable_file` files are left to the variable carry-over
+pub(crate) fn find_conflicts(
+    files: &[PlannedFile],
+    previous: &Receipt,
+    policy: ModifiedFilePolicy,
+    is_variable_file: impl Fn(&Path) -> bool,
+) -> Vec<FileConflict> {
+    let mut conflicts = vec![];
+    for file in files {
+        if file.entry.is_dir
+            || file.component != "Skins"
+            || !file.dest.is_file()
+            || is_variable_file(&file.dest)
+        {
+            continue;
+        }
+        let dest = file.dest.to_string_lossy().to_lowercase();
+        let recorded = match previous
+            .files()
+            .find(|f| f.path.to_string_lossy().to_lowercase() == dest)
+        {
+            Some(recorded) => recorded,
+            None => continue,
+        };
+        let changed = match receipts::sha256_file(&file.dest) {
+            Ok(sha256) => sha256 != recorded.sha256,
+            Err(_) => false,
+        };
+        if !changed {
+            continue;
+        }
+        conflicts.push(FileConflict {
+            path: file.dest.to_owned(),
+            policy,
+            side_copy: match policy {
+                ModifiedFilePolicy::SideCopy => Some(side_copy_path(&file.dest)),
+                _ => None,
+            },
+            recorded: recorded.clone(),
+        });
+    }
+    conflicts
+}
+
+/// Clock.ini is kept as Clock.user.ini.
+pub fn side_copy_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.user.{}", stem, extension.to_string_lossy()),
+        None => format!("{}.user", stem),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::ArchiveEntry;
+    use crate::plan::FileAction;
+    use crate::plan::InstallPlan;
+    use crate::receipts::ReceiptComponent;
+    use crate::receipts::ReceiptPolicy;
+    use crate::variables::VariablePolicy;
+    use std::fs;
+
+    fn planned(dest: &Path) -> PlannedFile {
+        PlannedFile {
+            component: "Skins",
+            entry: ArchiveEntry {
+                index: 0,
+                path: PathBuf::new(),
+                is_dir: false,
+                size: 0,
+            },
+            dest: dest.to_owned(),
+            action: FileAction::Overwrite,
+        }
+    }
+
+    fn previous(files: &[&Path]) -> Receipt {
+        Receipt {
+            name: "Suite".to_owned(),
+            author: None,
+            version: Some("1.0".to_owned()),
+            archive: PathBuf::from("Suite.rmskin"),
+            archive_sha256: "00".to_owned(),
+            installed_at: 0,
+            policy: ReceiptPolicy {
+                variables: VariablePolicy::Keep,
+                backup: true,
+                merge_skins: false,
+                force_plugins: false,
+                ignore_requirements: false,
+                allow_addons: false,
+                modified_files: ModifiedFilePolicy::SideCopy,
+            },
+            components: vec![ReceiptComponent {
+                component: "Skins".to_owned(),
+                name: "Suite".to_owned(),
+                files: files
+                    .iter()
+                    .map(|path| ReceiptFile {
+                        path: path.to_path_buf(),
+                        entry: PathBuf::new(),
+                        size: 0,
+                        sha256: receipts::sha256_file(path).unwrap(),
+                    })
+                    .collect(),
+            }],
+            variable_files: vec![],
+            kept_files: vec![],
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn keeps_or_side_copies_files_changed_since_the_receipt() {
+        let folder = std::env::temp_dir().join(format!("conflicts-test-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let clock = folder.join("Clock.ini");
+        let logo = folder.join("Logo.ini");
+        let variables = folder.join("Variables.inc");
+        for path in [&clock, &logo, &variables] {
+            fs::write(path, "[Rainmeter]").unwrap();
+        }
+        let previous = previous(&[&clock, &logo, &variables]);
+        fs::write(&clock, "[Rainmeter]\nUpdate=500").unwrap();
+        fs::write(&variables, "[Variables]").unwrap();
+
+        let files = vec![planned(&clock), planned(&logo), planned(&variables)];
+        let find = |policy| find_conflicts(&files, &previous, policy, |path| path == variables);
+        let side_copied = find(ModifiedFilePolicy::SideCopy);
+        let kept = find(ModifiedFilePolicy::Keep);
+        let overwritten = find(ModifiedFilePolicy::Overwrite);
+        fs::remove_dir_all(&folder).unwrap();
+
+        // only the changed skin file, the variable file is carried over instead
+        assert_eq!(side_copied.len(), 1);
+        assert_eq!(side_copied[0].path, clock);
+        assert_eq!(
+            side_copied[0].side_copy,
+            Some(folder.join("Clock.user.ini"))
+        );
+        assert_eq!(kept[0].side_copy, None);
+
+        let action = |conflicts: Vec<FileConflict>| {
+            let plan = InstallPlan {
+                files: vec![planned(&clock), planned(&logo)],
+                conflicts,
+                ..Default::default()
+            };
+            let text = plan.to_string();
+            let line = |dest: &Path| {
+                let dest = dest.to_string_lossy();
+                let line = text.lines().find(|line| line.ends_with(&*dest)).unwrap();
+                line.strip_suffix(&*dest).unwrap().trim().to_owned()
+            };
+            (line(&clock), line(&logo))
+        };
+        assert_eq!(
+            action(side_copied),
+            ("side copy".to_owned(), "overwrite".to_owned())
+        );
+        assert_eq!(action(kept), ("keep".to_owned(), "overwrite".to_owned()));
+        assert_eq!(
+            action(overwritten),
+            ("overwrite".to_owned(), "overwrite".to_owned())
+        );
+    }
+
+    #[test]
+    fn names_side_copies() {
+        let path: PathBuf = ["Suite", "Clock", "Clock.ini"].iter().collect();
+        let expected: PathBuf = ["Suite", "Clock", "Clock.user.ini"].iter().collect();
+        assert_eq!(side_copy_path(&path), expected);
+        assert_eq!(
+            side_copy_path(Path::new("README")),
+            PathBuf::from("README.user")
+        );
+        assert_eq!(
+            "Side-Copy".parse::<ModifiedFilePolicy>(),
+            Ok(ModifiedFilePolicy::SideCopy)
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::manifest::ManifestError;
+use crate::package::FooterError;
+use crate::pe::Machine;
+use crate::pe::PeError;
+use crate::requirements::UnmetRequirement;
+use crate::sanitize::SanitizeError;
+use std::fmt;
+use std::path::PathBuf;
+use zip::result::ZipError;
+
+/// Everything that can stop an install.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+    /// A step of the install failed on one of its files.
+    Install {
+        /// What was being done, e.g. "installing".
+        action: &'static str,
+        /// The file or folder it failed on.
+        path: PathBuf,
+        /// Why it failed.
+        error: std::io::Error,
+    },
+    /// Rainmeter is not installed in the standard location or was never run.
+    RainmeterNotFound,
+    /// Rainmeter.ini could not be read.
+    Settings(String),
+    /// The rmskin footer is missing or damaged.
+    Package(FooterError),
+    /// The package is a zip archive without the rmskin footer.
+    PlainZip,
+    /// The zip archive inside the package could not be read.
+    Zip(ZipError),
+    /// The archive holds entries that are unsafe to extract.
+    UnsafeEntries(SanitizeError),
+    /// The package has no RMSKIN.ini.
+    ManifestMissing,
+    /// RMSKIN.ini could not be parsed.
+    Manifest(ManifestError),
+    /// Rainmeter.exe is built for an architecture without a plugin folder.
+    UnsupportedArchitecture(Machine),
+    /// Rainmeter.exe could not be read as a PE image.
+    Pe(PeError),
+    /// Plugins shipped in the wrong architecture folder, one message per plugin.
+    WrongArchitecture(Vec<String>),
+    /// MinimumRainmeter or MinimumWindows is not met.
+    Requirements(Vec<UnmetRequirement>),
+    /// The package has addons but they were not allowed.
+    AddonsNotAllowed(Vec<String>),
+    /// Rainmeter did not close in time.
+    RainmeterRunning,
+    /// The install receipts could not be read.
+    Receipts(String),
+    /// The backup index could not be read.
+    Backups(String),
+    /// No receipt names the package.
+    NotInstalled(String),
+    /// A repair was given another archive than the one the package was installed from.
+    WrongArchive(String),
+    /// The installed package was pinned.
+    Pinned(String),
+    /// The package is older than the installed one and downgrades were not allowed.
+    Downgrade {
+        /// The installed version.
+        installed: String,
+        /// The package's version.
+        package: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Install {
+                action,
+                path,
+                error,
+            } => write!(f, "error {} {}: {}", action, path.display(), error),
+            Error::RainmeterNotFound => {
+                write!(f, "Rainmeter not installed or not run for the first time")
+            }
+            Error::Settings(e) => write!(f, "error reading Rainmeter settings: {}", e),
+            Error::Package(e) => write!(f, "{}", e),
+            Error::PlainZip => write!(
+                f,
+                "not an rmskin package: plain zip archive without the rmskin footer"
+            ),
+            Error::Zip(e) => write!(f, "error reading zip file: {}", e),
+            Error::UnsafeEntries(e) => write!(f, "{}", e),
+            Error::ManifestMissing => write!(f, "RMSKIN.ini not found in zip"),
+            Error::Manifest(e) => write!(f, "error parsing RMSKIN.ini: {}", e),
+            Error::UnsupportedArchitecture(machine) => {
+                write!(f, "unsupported Rainmeter architecture: {}", machine)
+            }
+            Error::Pe(e) => write!(f, "error reading Rainmeter.exe: {}", e),
+            Error::WrongArchitecture(messages) => {
+                write!(f, "plugins built for the wrong architecture:")?;
+                for message in messages {
+                    write!(f, "\n  {}", message)?;
+                }
+                Ok(())
+            }
+            Error::Requirements(unmet) => {
+                write!(
+                    f,
+                    "package requirements not met, use --ignore-requirements to install anyway:"
+                )?;
+                for requirement in unmet {
+                    write!(f, "\n  {}", requirement)?;
+                }
+                Ok(())
+            }
+            Error::AddonsNotAllowed(addons) => write!(
+                f,
+                "package contains addons: {}. Addons are executables, use --allow-addons to install them",
+                addons.join(", ")
+            ),
+            Error::RainmeterRunning => write!(
+                f,
+                "Rainmeter is running, please close Rainmeter before installing"
+            ),
+            Error::Receipts(e) => write!(f, "error reading install receipts: {}", e),
+            Error::Backups(e) => write!(f, "error reading backup index: {}", e),
+            Error::NotInstalled(package) => write!(f, "package {} is not installed", package),
+            Error::WrongArchive(package) => write!(
+                f,
+                "the archive is not the one {} was installed from",
+                package
+            ),
+            Error::Pinned(package) => write!(
+                f,
+                "package {} is pinned, unpin it first",
+                package
+            ),
+            Error::Downgrade { installed, package } => write!(
+                f,
+                "installed version {} is newer than the package's {}, use --allow-downgrade to install it anyway",
+                installed, package
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Install { error, .. } => Some(error),
+            Error::Package(e) => Some(e),
+            Error::Zip(e) => Some(e),
+            Error::UnsafeEntries(e) => Some(e),
+            Error::Manifest(e) => Some(e),
+            Error::Pe(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<FooterError> for Error {
+    fn from(e: FooterError) -> Self {
+        Error::Package(e)
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(e: ZipError) -> Self {
+        Error::Zip(e)
+    }
+}
+
+impl From<SanitizeError> for Error {
+    fn from(e: SanitizeError) -> Self {
+        Error::UnsafeEntries(e)
+    }
+}
+
+impl From<ManifestError> for Error {
+    fn from(e: ManifestError) -> Self {
+        Error::Manifest(e)
+    }
+}
+
+impl From<PeError> for Error {
+    fn from(e: PeError) -> Self {
+        Error::Pe(e)
+    }
+}
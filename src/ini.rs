@@ -0,0 +1,500 @@
+// reads and edits ini files the way Rainmeter reads them, without losing anything
+// the edit doesn't touch: comments, blank lines, key order, encoding and line endings
+// all stay as they were in the file
+//
+// parsing follows the Windows profile functions Rainmeter is built on:
+// - section and key names are case-insensitive and trimmed
+// - lines starting with ; are comments, lines without = are ignored
+// - only the first section with a name counts, and in it the first key with a name
+// - values are trimmed, a value enclosed in double quotes has them removed
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How the file was stored, kept when it is written back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8 without a byte order mark.
+    Utf8,
+    /// UTF-8 with a byte order mark.
+    Utf8Bom,
+    /// UTF-16 little endian with a byte order mark, what Rainmeter writes.
+    Utf16LeBom,
+    /// Anything that isn't valid UTF-8, kept byte for byte.
+    Ansi,
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    text: String,
+    // "\r\n", "\n" or "" for a last line without one
+    ending: &'static str,
+}
+
+enum LineKind<'a> {
+    Section(&'a str),
+    Entry {
+        key: &'a str,
+        // byte offset of the value in the line, after the = and any spaces
+        value_start: usize,
+    },
+    Other,
+}
+
+/// One line of the file the way it is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IniLine<'a> {
+    /// A `[Section]` header, the name without brackets.
+    Section(&'a str),
+    /// The value as it appears in the file, quotes included.
+    Entry {
+        /// The key as written.
+        key: &'a str,
+        /// The value, trimmed.
+        value: &'a str,
+    },
+    /// Blank lines, comments and anything else that is ignored.
+    Other(&'a str),
+}
+
+/// An ini file that keeps every line, so editing it changes only what was edited.
+#[derive(Debug, Clone)]
+pub struct IniFile {
+    encoding: Encoding,
+    newline: &'static str,
+    lines: Vec<Line>,
+}
+
+impl IniFile {
+    /// An empty file written with this encoding and line ending.
+    pub fn new(encoding: Encoding, newline: &'static str) -> IniFile {
+        IniFile {
+            encoding,
+            newline,
+            lines: vec![],
+        }
+    }
+
+    /// Reads the file in whatever encoding it is stored.
+    pub fn read(path: &Path) -> io::Result<IniFile> {
+        Ok(IniFile::from_bytes(&fs::read(path)?))
+    }
+
+    /// Writes the file back in its own encoding.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Decodes UTF-16 LE with a byte order mark and UTF-8 with or without one,
+    /// anything else as ANSI.
+    pub fn from_bytes(bytes: &[u8]) -> IniFile {
+        let (encoding, text) = decode(bytes);
+        IniFile::parse(&text, encoding)
+    }
+
+    /// Splits text already decoded from `encoding` into its lines.
+    pub fn parse(text: &str, encoding: Encoding) -> IniFile {
+        let mut lines = vec![];
+        let mut rest = text;
+        while !rest.is_empty() {
+            let (text, ending, next) = match rest.find('\n') {
+                Some(end) if end > 0 && rest.as_bytes()[end - 1] == b'\r' => {
+                    (&rest[..end - 1], "\r\n", &rest[end + 1..])
+                }
+                Some(end) => (&rest[..end], "\n", &rest[end + 1..]),
+                None => (rest, "", ""),
+            };
+            lines.push(Line {
+                text: text.to_owned(),
+                ending,
+            });
+            rest = next;
+        }
+
+        // new lines follow the file, windows line endings for a file without any
+        let newline = lines
+            .iter()
+            .map(|line| line.ending)
+            .find(|ending| !ending.is_empty())
+            .unwrap_or("\r\n");
+
+        IniFile {
+            encoding,
+            newline,
+            lines,
+        }
+    }
+
+    /// The file encoded the way it was read.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut text = String::new();
+        for line in &self.lines {
+            text.push_str(&line.text);
+            text.push_str(line.ending);
+        }
+        encode(&text, self.encoding)
+    }
+
+    /// The encoding the file was read in.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// The line ending new lines get, the file's own or "\r\n".
+    pub fn newline(&self) -> &'static str {
+        self.newline
+    }
+
+    /// Every line in file order, for reporting problems by line number.
+    pub fn lines(&self) -> impl Iterator<Item = IniLine<'_>> {
+        self.lines.iter().map(|line| match classify(&line.text) {
+            LineKind::Section(name) => IniLine::Section(name),
+            LineKind::Entry { key, value_start } => IniLine::Entry {
+                key,
+                value: line.text[value_start..].trim(),
+            },
+            LineKind::Other => IniLine::Other(&line.text),
+        })
+    }
+
+    /// Names of all sections in file order, the first spelling of each.
+    pub fn sections(&self) -> Vec<String> {
+        let mut sections: Vec<String> = vec![];
+        for line in &self.lines {
+            if let LineKind::Section(name) = classify(&line.text) {
+                if !sections.iter().any(|s| eq_name(s, name)) {
+                    sections.push(name.to_owned());
+                }
+            }
+        }
+        sections
+    }
+
+    /// Whether the section exists, names match case-insensitively.
+    pub fn has_section(&self, section: &str) -> bool {
+        self.section_range(section).is_some()
+    }
+
+    /// Keys as written and values as they appear in the file, quotes included.
+    pub fn entries(&self, section: &str) -> Vec<(String, String)> {
+        let mut entries: Vec<(String, String)> = vec![];
+        let range = match self.section_range(section) {
+            Some(range) => range,
+            None => return entries,
+        };
+        for line in &self.lines[range] {
+            if let LineKind::Entry { key, value_start } = classify(&line.text) {
+                if !entries.iter().any(|(k, _)| eq_name(k, key)) {
+                    entries.push((key.to_owned(), line.text[value_start..].trim().to_owned()));
+                }
+            }
+        }
+        entries
+    }
+
+    /// The value as it appears in the file, quotes included.
+    pub fn get_raw(&self, section: &str, key: &str) -> Option<String> {
+        let index = self.find_key(section, key)?;
+        let text = &self.lines[index].text;
+        match classify(text) {
+            LineKind::Entry { value_start, .. } => Some(text[value_start..].trim().to_owned()),
+            _ => None,
+        }
+    }
+
+    /// The value the way Rainmeter sees it.
+    pub fn get(&self, section: &str, key: &str) -> Option<String> {
+        self.get_raw(section, key)
+            .map(|value| unquote(&value).to_owned())
+    }
+
+    /// Replaces the value in place, or adds the key at the end of the section.
+    /// The section is added at the end of the file if it doesn't exist.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        if let Some(index) = self.find_key(section, key) {
+            let line = &mut self.lines[index];
+            if let LineKind::Entry { value_start, .. } = classify(&line.text) {
+                // keep the spaces that followed the old value
+                let old = &line.text[value_start..];
+                let trailing = &old[old.trim_end().len()..];
+                line.text = format!("{}{}{}", &line.text[..value_start], value, trailing);
+            }
+            return;
+        }
+
+        let entry = format!("{}={}", key, value);
+        match self.section_range(section) {
+            Some(range) => {
+                // after the last line with content so blank lines before the next section stay
+                let mut at = range.start;
+                for index in range {
+                    if !self.lines[index].text.trim().is_empty() {
+                        at = index + 1;
+                    }
+                }
+                self.insert_line(at, entry);
+            }
+            None => {
+                if self
+                    .lines
+                    .last()
+                    .is_some_and(|line| !line.text.trim().is_empty())
+                {
+                    self.insert_line(self.lines.len(), "".to_owned());
+                }
+                self.insert_line(self.lines.len(), format!("[{}]", section));
+                self.insert_line(self.lines.len(), entry);
+            }
+        }
+    }
+
+    /// Removes the key from the section, returns whether it was there.
+    pub fn remove(&mut self, section: &str, key: &str) -> bool {
+        match self.find_key(section, key) {
+            Some(index) => {
+                let line = self.lines.remove(index);
+                // the new last line takes over a missing final line ending
+                if index == self.lines.len() && line.ending.is_empty() {
+                    if let Some(last) = self.lines.last_mut() {
+                        last.ending = "";
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert_line(&mut self, at: usize, text: String) {
+        let mut ending = self.newline;
+        if at == self.lines.len() {
+            // keep a missing final line ending missing
+            if let Some(last) = self.lines.last_mut() {
+                if last.ending.is_empty() {
+                    last.ending = self.newline;
+                    ending = "";
+                }
+            }
+        }
+        self.lines.insert(at, Line { text, ending });
+    }
+
+    // lines below the first header with this name, up to the next header
+    fn section_range(&self, section: &str) -> Option<std::ops::Range<usize>> {
+        let start = self.lines.iter().position(
+            |line| matches!(classify(&line.text), LineKind::Section(name) if eq_name(name, section)),
+        )? + 1;
+        let end = self.lines[start..]
+            .iter()
+            .position(|line| matches!(classify(&line.text), LineKind::Section(_)))
+            .map(|end| start + end)
+            .unwrap_or(self.lines.len());
+        Some(start..end)
+    }
+
+    fn find_key(&self, section: &str, key: &str) -> Option<usize> {
+        self.section_range(section)?.find(|index| {
+            matches!(classify(&self.lines[*index].text), LineKind::Entry { key: k, .. } if eq_name(k, key))
+        })
+    }
+}
+
+fn classify(text: &str) -> LineKind<'_> {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with(';') {
+        return LineKind::Other;
+    }
+    if let Some(header) = trimmed.strip_prefix('[') {
+        if let Some(end) = header.find(']') {
+            return LineKind::Section(header[..end].trim());
+        }
+    }
+    match text.find('=') {
+        Some(equals) if !text[..equals].trim().is_empty() => {
+            let after = &text[equals + 1..];
+            LineKind::Entry {
+                key: text[..equals].trim(),
+                value_start: equals + 1 + (after.len() - after.trim_start().len()),
+            }
+        }
+        _ => LineKind::Other,
+    }
+}
+
+fn eq_name(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b) || a.to_lowercase() == b.to_lowercase()
+}
+
+/// The value without the double quotes around it, like Rainmeter reads it.
+pub fn unquote(value: &str) -> &str {
+    if value.len() > 1 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+fn decode(bytes: &[u8]) -> (Encoding, String) {
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = rest
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        return (Encoding::Utf16LeBom, String::from_utf16_lossy(&units));
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return (
+            Encoding::Utf8Bom,
+            String::from_utf8_lossy(rest).into_owned(),
+        );
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (Encoding::Utf8, text.to_owned()),
+        // every byte maps to the char with the same value so it is written back unchanged
+        Err(_) => (Encoding::Ansi, bytes.iter().map(|b| *b as char).collect()),
+    }
+}
+
+fn encode(text: &str, encoding: Encoding) -> Vec<u8> {
+    match encoding {
+        Encoding::Utf8 => text.as_bytes().to_vec(),
+        Encoding::Utf8Bom => [&[0xEF, 0xBB, 0xBF], text.as_bytes()].concat(),
+        Encoding::Utf16LeBom => {
+            let mut bytes = vec![0xFF, 0xFE];
+            for unit in text.encode_utf16() {
+                bytes.extend_from_slice(&unit.to_le_bytes());
+            }
+            bytes
+        }
+        Encoding::Ansi => text
+            .chars()
+            .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VARIABLES: &str = "; settings of the suite\r\n[Variables]\r\nColor=255,255,255\r\n  Font Face = \"Segoe UI\"  \r\n\r\n; layout\r\nWidth=200\r\n\r\n[Other]\r\nColor=0,0,0\r\n";
+
+    #[test]
+    fn reads_like_rainmeter() {
+        let ini = IniFile::parse(VARIABLES, Encoding::Utf8);
+        assert_eq!(
+            ini.get("variables", "color").as_deref(),
+            Some("255,255,255")
+        );
+        assert_eq!(
+            ini.get("Variables", "font face").as_deref(),
+            Some("Segoe UI")
+        );
+        assert_eq!(
+            ini.get_raw("Variables", "Font Face").as_deref(),
+            Some("\"Segoe UI\"")
+        );
+        assert_eq!(ini.get("Variables", "Missing"), None);
+        assert_eq!(ini.sections(), vec!["Variables", "Other"]);
+        assert_eq!(
+            ini.entries("Variables"),
+            vec![
+                ("Color".to_owned(), "255,255,255".to_owned()),
+                ("Font Face".to_owned(), "\"Segoe UI\"".to_owned()),
+                ("Width".to_owned(), "200".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn first_key_and_section_win() {
+        let ini = IniFile::parse(
+            "[Variables]\nA=1\na=2\n;B=3\n[variables]\nB=4\n",
+            Encoding::Utf8,
+        );
+        assert_eq!(ini.get("Variables", "A").as_deref(), Some("1"));
+        assert_eq!(ini.get("Variables", "B"), None);
+        assert_eq!(ini.entries("Variables").len(), 1);
+    }
+
+    #[test]
+    fn round_trips_unchanged() {
+        for encoding in [Encoding::Utf8, Encoding::Utf8Bom, Encoding::Utf16LeBom] {
+            let bytes = encode("[Variables]\r\nText=Grüße ✓\nNoEnding=1", encoding);
+            let ini = IniFile::from_bytes(&bytes);
+            assert_eq!(ini.encoding(), encoding);
+            assert_eq!(ini.to_bytes(), bytes);
+        }
+
+        let ansi = b"[Variables]\r\nText=Gr\xfc\xdfe\r\n".to_vec();
+        let ini = IniFile::from_bytes(&ansi);
+        assert_eq!(ini.encoding(), Encoding::Ansi);
+        assert_eq!(ini.to_bytes(), ansi);
+    }
+
+    #[test]
+    fn set_replaces_value_in_place() {
+        let mut ini = IniFile::parse(VARIABLES, Encoding::Utf8);
+        ini.set("Variables", "FONT FACE", "\"Arial\"");
+        ini.set("Variables", "Color", "0,0,0");
+        assert_eq!(
+            String::from_utf8(ini.to_bytes()).unwrap(),
+            VARIABLES.replace("\"Segoe UI\"  ", "\"Arial\"  ").replacen(
+                "Color=255,255,255",
+                "Color=0,0,0",
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn set_appends_after_last_entry() {
+        let mut ini = IniFile::parse(VARIABLES, Encoding::Utf8);
+        ini.set("Variables", "Height", "50");
+        assert_eq!(
+            String::from_utf8(ini.to_bytes()).unwrap(),
+            VARIABLES.replace("Width=200\r\n", "Width=200\r\nHeight=50\r\n")
+        );
+    }
+
+    #[test]
+    fn set_adds_missing_section() {
+        let mut ini = IniFile::parse("[Rainmeter]\nUpdate=1000", Encoding::Utf8);
+        ini.set("Variables", "A", "1");
+        assert_eq!(
+            String::from_utf8(ini.to_bytes()).unwrap(),
+            "[Rainmeter]\nUpdate=1000\n\n[Variables]\nA=1"
+        );
+
+        let mut ini = IniFile::new(Encoding::Utf16LeBom, "\r\n");
+        ini.set("Variables", "A", "1");
+        assert_eq!(
+            ini.to_bytes(),
+            encode("[Variables]\r\nA=1\r\n", Encoding::Utf16LeBom)
+        );
+    }
+
+    #[test]
+    fn large_sections_are_not_truncated() {
+        let mut text = "[Variables]\r\n".to_owned();
+        for i in 0..5000 {
+            text.push_str(&format!("Variable{}=value number {}\r\n", i, i));
+        }
+        let ini = IniFile::parse(&text, Encoding::Utf16LeBom);
+        let entries = ini.entries("Variables");
+        assert_eq!(entries.len(), 5000);
+        assert_eq!(entries[4999].1, "value number 4999");
+    }
+
+    #[test]
+    fn remove_keeps_final_line_ending() {
+        let mut ini = IniFile::parse("[Variables]\nA=1\nB=2", Encoding::Utf8);
+        assert!(ini.remove("Variables", "b"));
+        assert!(!ini.remove("Variables", "b"));
+        assert_eq!(
+            String::from_utf8(ini.to_bytes()).unwrap(),
+            "[Variables]\nA=1"
+        );
+    }
+}
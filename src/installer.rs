@@ -0,0 +1,630 @@
+use crate::archive::ArchiveEntry;
+use crate::archive::PackageArchive;
+use crate::backups::BackupFormat;
+use crate::backups::BackupRetention;
+use crate::conflicts::ModifiedFilePolicy;
+use crate::ini::IniFile;
+use crate::manifest::PackageManifest;
+use crate::migrations;
+use crate::migrations::Migration;
+use crate::package;
+use crate::package::PackageFormat;
+use crate::pe::Machine;
+use crate::plan;
+use crate::plan::CarriedVariables;
+use crate::plan::InstallPlan;
+use crate::plan::InstallProgress;
+use crate::plugins;
+use crate::rainmeter;
+use crate::receipts;
+use crate::receipts::ReceiptStore;
+use crate::requirements;
+use crate::requirements::UnmetRequirement;
+use crate::variables;
+use crate::variables::VariablePolicy;
+use crate::version::PackageVersion;
+use crate::Error;
+use crate::RainmeterSettings;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::path::PathBuf;
+
+// what the package holds and how it should be installed, shared by planning and executing
+#[derive(Debug)]
+pub(crate) struct InstallOptions {
+    pub was_running: bool,
+    pub plugins: Vec<String>,
+    pub skins: Vec<String>,
+    pub layouts: Vec<String>,
+    pub addons: Vec<String>,
+    pub manifest: PackageManifest,
+    pub force_plugins: bool,
+    pub plugin_machine: Machine,
+    pub backup: bool,
+    pub backup_retention: BackupRetention,
+    pub backup_format: BackupFormat,
+    pub ignore_requirements: bool,
+    pub allow_addons: bool,
+    pub variable_policy: VariablePolicy,
+    pub variable_sections: Vec<String>,
+    pub modified_files: ModifiedFilePolicy,
+    pub migrations: Vec<Migration>,
+}
+
+/// Builds an install of one .rmskin package.
+///
+/// ```no_run
+/// use rainmeter_skin_installer::Installer;
+///
+/// let mut prepared = Installer::new("Suite_1.0.rmskin").backup(false).open()?;
+/// println!("{}", prepared.plan()?);
+/// prepared.install()?;
+/// # Ok::<(), rainmeter_skin_installer::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Installer {
+    skinfile: PathBuf,
+    settings: Option<RainmeterSettings>,
+    variable_policy: VariablePolicy,
+    variable_sections: Option<Vec<String>>,
+    modified_files: ModifiedFilePolicy,
+    backup: bool,
+    backup_retention: BackupRetention,
+    backup_format: BackupFormat,
+    ignore_requirements: bool,
+    force_plugins: bool,
+    allow_addons: bool,
+    allow_downgrade: bool,
+}
+
+impl Installer {
+    /// Starts an install of the package at `skinfile` with the default options:
+    /// backups on, variables kept, changed skin files kept as side copies,
+    /// requirements enforced, older plugins kept, addons and downgrades refused.
+    pub fn new(skinfile: impl Into<PathBuf>) -> Installer {
+        Installer {
+            skinfile: skinfile.into(),
+            settings: None,
+            variable_policy: VariablePolicy::Keep,
+            variable_sections: None,
+            modified_files: ModifiedFilePolicy::SideCopy,
+            backup: true,
+            backup_retention: BackupRetention::default(),
+            backup_format: BackupFormat::Folder,
+            ignore_requirements: false,
+            force_plugins: false,
+            allow_addons: false,
+            allow_downgrade: false,
+        }
+    }
+
+    /// Installs into the given Rainmeter instead of the detected standard one.
+    pub fn settings(mut self, settings: RainmeterSettings) -> Installer {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// What happens to the user's values in the VariableFiles.
+    pub fn variable_policy(mut self, policy: VariablePolicy) -> Installer {
+        self.variable_policy = policy;
+        self
+    }
+
+    /// Sections of the variable files to carry over, overriding VariableSections
+    /// in RMSKIN.ini. `*` matches any run of characters, so `["*"]` keeps every section.
+    pub fn variable_sections(mut self, sections: Vec<String>) -> Installer {
+        self.variable_sections = Some(sections);
+        self
+    }
+
+    /// What happens to skin files the user changed since the previous install.
+    pub fn modified_files(mut self, policy: ModifiedFilePolicy) -> Installer {
+        self.modified_files = policy;
+        self
+    }
+
+    /// Copies replaced skins and addons to a new generation in `Skins\@Backup` first.
+    pub fn backup(mut self, backup: bool) -> Installer {
+        self.backup = backup;
+        self
+    }
+
+    /// How many backup generations of each skin and addon are kept, five by default.
+    pub fn backup_retention(mut self, retention: BackupRetention) -> Installer {
+        self.backup_retention = retention;
+        self
+    }
+
+    /// Writes each backup as a compressed zip that also holds the replaced layouts,
+    /// plugins and the skin's Rainmeter.ini sections, instead of copying the folder.
+    pub fn backup_format(mut self, format: BackupFormat) -> Installer {
+        self.backup_format = format;
+        self
+    }
+
+    /// Installs even if MinimumRainmeter or MinimumWindows is not met.
+    pub fn ignore_requirements(mut self, ignore_requirements: bool) -> Installer {
+        self.ignore_requirements = ignore_requirements;
+        self
+    }
+
+    /// Replaces installed plugins even with older versions.
+    pub fn force_plugins(mut self, force_plugins: bool) -> Installer {
+        self.force_plugins = force_plugins;
+        self
+    }
+
+    /// Allows installing the package's addons, which are executables.
+    pub fn allow_addons(mut self, allow_addons: bool) -> Installer {
+        self.allow_addons = allow_addons;
+        self
+    }
+
+    /// Installs even if the installed package has a newer Version.
+    pub fn allow_downgrade(mut self, allow_downgrade: bool) -> Installer {
+        self.allow_downgrade = allow_downgrade;
+        self
+    }
+
+    /// Reads and checks the package. Nothing is written yet.
+    pub fn open(self) -> Result<PreparedInstall, Error> {
+        let settings = match self.settings {
+            Some(settings) => settings,
+            None => RainmeterSettings::detect()?,
+        };
+
+        let mut install_options = InstallOptions {
+            was_running: false,
+            plugins: vec![],
+            skins: vec![],
+            layouts: vec![],
+            addons: vec![],
+            manifest: PackageManifest::default(),
+            force_plugins: self.force_plugins,
+            plugin_machine: settings.machine()?,
+            backup: self.backup,
+            backup_retention: self.backup_retention,
+            backup_format: self.backup_format,
+            ignore_requirements: self.ignore_requirements,
+            allow_addons: self.allow_addons,
+            variable_policy: self.variable_policy,
+            variable_sections: vec![],
+            modified_files: self.modified_files,
+            migrations: vec![],
+        };
+        let mut warnings = vec![];
+
+        let mut archive = read_archive(&self.skinfile, &mut install_options, &mut warnings)?;
+        check_plugins(&mut archive, &install_options)?;
+        read_options(&mut archive, &mut install_options)?;
+        for warning in &install_options.manifest.warnings {
+            warnings.push(warning.to_string());
+        }
+        install_options.migrations = migrations::read(&mut archive, &mut warnings)?;
+
+        // the caller overrides the package, [Variables] if neither names any
+        install_options.variable_sections = match self.variable_sections {
+            Some(sections) => sections,
+            None => install_options.manifest.variable_sections.clone(),
+        };
+        if install_options.variable_sections.is_empty() {
+            install_options.variable_sections = vec![variables::DEFAULT_SECTION.to_owned()];
+        }
+
+        let unmet = check_requirements(&install_options, &settings, &mut warnings);
+        if !unmet.is_empty() && !self.ignore_requirements {
+            return Err(Error::Requirements(unmet));
+        }
+        for requirement in &unmet {
+            warnings.push(format!("ignoring unmet requirement: {}", requirement));
+        }
+
+        check_installed(
+            &install_options,
+            &settings,
+            self.allow_downgrade,
+            &mut warnings,
+        )?;
+
+        // addons are executables, only install them when asked to
+        if !install_options.addons.is_empty() && !self.allow_addons {
+            return Err(Error::AddonsNotAllowed(install_options.addons.clone()));
+        }
+
+        Ok(PreparedInstall {
+            skinfile: self.skinfile,
+            archive,
+            settings,
+            install_options,
+            warnings,
+        })
+    }
+}
+
+/// A package that was read and checked, ready to plan or install.
+#[derive(Debug)]
+pub struct PreparedInstall {
+    skinfile: PathBuf,
+    archive: PackageArchive,
+    settings: RainmeterSettings,
+    install_options: InstallOptions,
+    warnings: Vec<String>,
+}
+
+impl PreparedInstall {
+    /// The package's RMSKIN.ini.
+    pub fn manifest(&self) -> &PackageManifest {
+        &self.install_options.manifest
+    }
+
+    /// The Rainmeter the package is installed into.
+    pub fn settings(&self) -> &RainmeterSettings {
+        &self.settings
+    }
+
+    /// The architecture of the installed Rainmeter, plugins are taken from its folder.
+    pub fn plugin_machine(&self) -> Machine {
+        self.install_options.plugin_machine
+    }
+
+    /// The skin roots the package ships.
+    pub fn skins(&self) -> &[String] {
+        &self.install_options.skins
+    }
+
+    /// The layouts the package ships.
+    pub fn layouts(&self) -> &[String] {
+        &self.install_options.layouts
+    }
+
+    /// The plugin dlls the package ships for this architecture.
+    pub fn plugins(&self) -> &[String] {
+        &self.install_options.plugins
+    }
+
+    /// The addons the package ships.
+    pub fn addons(&self) -> &[String] {
+        &self.install_options.addons
+    }
+
+    /// What happens to the user's values in the VariableFiles.
+    pub fn variable_policy(&self) -> VariablePolicy {
+        self.install_options.variable_policy
+    }
+
+    /// Problems found while reading the package that don't stop the install.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Works out everything the install would do without changing anything.
+    pub fn plan(&mut self) -> Result<InstallPlan, Error> {
+        self.install_options.was_running = rainmeter::is_rainmeter_running();
+        plan::build_plan(&mut self.archive, &self.install_options, &self.settings)
+    }
+
+    /// Closes Rainmeter, installs the package, records its receipt and starts
+    /// Rainmeter again, also after a failed install if it was running. Returns
+    /// the plan that was carried out.
+    pub fn install(&mut self) -> Result<InstallPlan, Error> {
+        self.install_with(|_| true, |_| ())
+    }
+
+    /// Like [`install`](Self::install), asking `prompt` whether to carry over the values
+    /// of each variable file when the policy is [`VariablePolicy::PromptFile`].
+    pub fn install_with_prompt(
+        &mut self,
+        prompt: impl FnMut(&CarriedVariables) -> bool,
+    ) -> Result<InstallPlan, Error> {
+        self.install_with(prompt, |_| ())
+    }
+
+    /// Like [`install_with_prompt`](Self::install_with_prompt), passing each step
+    /// and every warning to `progress` as it happens.
+    pub fn install_with(
+        &mut self,
+        mut prompt: impl FnMut(&CarriedVariables) -> bool,
+        mut progress: impl FnMut(InstallProgress),
+    ) -> Result<InstallPlan, Error> {
+        if !rainmeter::close_rainmeter_if_running(&mut self.install_options.was_running) {
+            return Err(Error::RainmeterRunning);
+        }
+
+        // planned after closing rainmeter so the variables it saves on exit are kept
+        let installed = plan::build_plan(&mut self.archive, &self.install_options, &self.settings)
+            .and_then(|mut plan| {
+                if plan.variable_policy == VariablePolicy::PromptFile {
+                    plan.variables.retain(|variables| prompt(variables));
+                }
+                plan::execute_plan(&mut self.archive, &plan, &self.settings, &mut progress)?;
+                Ok(plan)
+            });
+        let plan = match installed {
+            Ok(plan) => plan,
+            Err(error) => {
+                // a failed install still gives the user their Rainmeter back
+                if self.install_options.was_running {
+                    if let Err(e) = rainmeter::start_rainmeter(&self.settings, None) {
+                        progress(InstallProgress::Warning(format!(
+                            "could not start Rainmeter: {}",
+                            e
+                        )));
+                    }
+                }
+                return Err(error);
+            }
+        };
+        let recorded =
+            receipts::record_install(&self.settings, &self.skinfile, &self.install_options, &plan);
+
+        if let Err(e) = rainmeter::start_rainmeter(&self.settings, plan.load.as_ref()) {
+            progress(InstallProgress::Warning(format!(
+                "could not start Rainmeter: {}",
+                e
+            )));
+        }
+        recorded?;
+
+        Ok(plan)
+    }
+}
+
+fn read_archive(
+    skinfile: &Path,
+    install_options: &mut InstallOptions,
+    warnings: &mut Vec<String>,
+) -> Result<PackageArchive, Error> {
+    // check the rmskin footer before trusting the file as a zip
+    match package::read_package_format(skinfile)? {
+        PackageFormat::Rmskin { .. } => (),
+        PackageFormat::PlainZip => return Err(Error::PlainZip),
+    };
+
+    let archive = PackageArchive::open(skinfile)?;
+
+    let mut found_rmskin = false;
+    let plugin_folder = install_options
+        .plugin_machine
+        .plugin_folder()
+        .unwrap_or("64bit");
+    let mut other_plugin_folders: Vec<String> = vec![];
+
+    // classify the contents of the zip file, nothing is written yet
+    for entry in &archive.entries {
+        let (component, name, extension) = parse_zip_item(entry.path.to_str().unwrap());
+        if component.eq("Skins") && !name.is_empty() {
+            install_options.skins.push(name.to_owned());
+        }
+        if component.eq("Layouts") && !name.is_empty() {
+            install_options.layouts.push(name.to_owned());
+        }
+        if component.eq("Addons") && !name.is_empty() {
+            install_options.addons.push(name.to_owned());
+        }
+        if component.eq("Plugins") {
+            if name.eq_ignore_ascii_case(plugin_folder) && extension.eq("dll") {
+                let plugin_name = entry.path.file_name().unwrap().to_str().unwrap().to_owned();
+                install_options.plugins.push(plugin_name);
+            } else if !other_plugin_folders.contains(&name) {
+                // only plugins matching the installed Rainmeter are used
+                other_plugin_folders.push(name.to_owned());
+            }
+        }
+        if name.eq("RMSKIN.ini") {
+            found_rmskin = true;
+        }
+    }
+
+    if !found_rmskin {
+        return Err(Error::ManifestMissing);
+    }
+
+    if install_options.plugins.is_empty() && !other_plugin_folders.is_empty() {
+        warnings.push(format!(
+            "package has plugins for {} but none for {}, plugins will not be installed",
+            other_plugin_folders.join(", "),
+            plugin_folder
+        ));
+    }
+
+    install_options.skins.sort();
+    install_options.skins.dedup();
+    install_options.layouts.sort();
+    install_options.layouts.dedup();
+    install_options.addons.sort();
+    install_options.addons.dedup();
+    install_options.plugins.sort();
+    install_options.plugins.dedup();
+
+    Ok(archive)
+}
+
+pub(crate) fn plugin_entry(
+    archive: &PackageArchive,
+    install_options: &InstallOptions,
+    plugin: &str,
+) -> Option<ArchiveEntry> {
+    let plugin_folder = install_options
+        .plugin_machine
+        .plugin_folder()
+        .unwrap_or("64bit");
+    archive
+        .entries
+        .iter()
+        .find(|entry| {
+            let (component, name, _) = parse_zip_item(entry.path.to_str().unwrap());
+            component.eq("Plugins")
+                && name.eq_ignore_ascii_case(plugin_folder)
+                && entry.path.file_name() == Some(OsStr::new(plugin))
+        })
+        .cloned()
+}
+
+fn check_plugins(
+    archive: &mut PackageArchive,
+    install_options: &InstallOptions,
+) -> Result<(), Error> {
+    let mut mismatched = vec![];
+    for plugin in &install_options.plugins[..] {
+        let entry = plugin_entry(archive, install_options, plugin).unwrap();
+        let data = archive.read(&entry)?;
+        if let Err(e) = plugins::check_machine(plugin, &data, install_options.plugin_machine) {
+            mismatched.push(e);
+        }
+    }
+
+    if !mismatched.is_empty() {
+        return Err(Error::WrongArchitecture(mismatched));
+    }
+
+    Ok(())
+}
+
+fn read_options(
+    archive: &mut PackageArchive,
+    install_options: &mut InstallOptions,
+) -> Result<(), Error> {
+    let entry = match archive.find(Path::new("RMSKIN.ini")) {
+        Some(entry) => entry,
+        None => return Err(Error::ManifestMissing),
+    };
+
+    let content = archive.read(&entry)?;
+    install_options.manifest = PackageManifest::from_bytes(&content)?;
+
+    Ok(())
+}
+
+fn check_requirements(
+    install_options: &InstallOptions,
+    rainmeter_settings: &RainmeterSettings,
+    warnings: &mut Vec<String>,
+) -> Vec<UnmetRequirement> {
+    let manifest = &install_options.manifest;
+
+    // only read the binaries when the package asks for it
+    let rainmeter = match manifest.minimum_rainmeter {
+        Some(_) => requirements::rainmeter_version(
+            Path::new(&rainmeter_settings.application_path),
+            warnings,
+        ),
+        None => None,
+    };
+    let windows = match manifest.minimum_windows {
+        Some(_) => requirements::windows_version(warnings),
+        None => None,
+    };
+
+    requirements::unmet_requirements(manifest, rainmeter.as_ref(), windows.as_ref())
+}
+
+// refuses to replace a pinned package or an installed version newer than the package
+fn check_installed(
+    install_options: &InstallOptions,
+    rainmeter_settings: &RainmeterSettings,
+    allow_downgrade: bool,
+    warnings: &mut Vec<String>,
+) -> Result<(), Error> {
+    let manifest = &install_options.manifest;
+    let store = ReceiptStore::load(rainmeter_settings)?;
+    let receipt = store.find_owner(
+        manifest.name.as_deref(),
+        &install_options.skins,
+        manifest.merge_skins,
+    );
+    if let Some(receipt) = receipt.filter(|r| r.pinned) {
+        return Err(Error::Pinned(receipt.name.to_owned()));
+    }
+
+    let version = match &manifest.version {
+        Some(version) => version,
+        None => return Ok(()),
+    };
+    // skins installed by hand or by Rainmeter's own installer only have their [Metadata],
+    // the root a MergeSkins package adds to holds another package's
+    let installed = match receipt.and_then(|r| r.version.clone()) {
+        Some(installed) => installed,
+        None if manifest.merge_skins => return Ok(()),
+        None => match metadata_version(rainmeter_settings, &install_options.skins) {
+            Some(installed) => installed,
+            None => return Ok(()),
+        },
+    };
+
+    match (
+        installed.parse::<PackageVersion>(),
+        version.parse::<PackageVersion>(),
+    ) {
+        (Ok(old), Ok(new)) if new < old => {
+            if !allow_downgrade {
+                return Err(Error::Downgrade {
+                    installed,
+                    package: version.to_owned(),
+                });
+            }
+            warnings.push(format!(
+                "downgrading from version {} to {}",
+                installed, version
+            ));
+        }
+        (Ok(_), Ok(_)) => (),
+        _ => warnings.push(format!(
+            "can't compare installed version \"{}\" with \"{}\", not checking for a downgrade",
+            installed, version
+        )),
+    }
+    Ok(())
+}
+
+// the [Metadata] Version of the first skin in the installed skin roots that has one
+fn metadata_version(rainmeter_settings: &RainmeterSettings, skins: &[String]) -> Option<String> {
+    for skin in skins {
+        let mut files = plan::list_files(&Path::new(&rainmeter_settings.skins_path).join(skin));
+        files.sort();
+        for file in files {
+            let is_skin = file
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("ini"));
+            if !is_skin {
+                continue;
+            }
+            let version = IniFile::read(&file)
+                .ok()
+                .and_then(|ini| ini.get("Metadata", "Version"));
+            if let Some(version) = version.filter(|v| !v.is_empty()) {
+                return Some(version);
+            }
+        }
+    }
+    None
+}
+
+fn parse_zip_item(item: &str) -> (String, String, String) {
+    let mut component: String = "".to_owned();
+    let mut name: String = "".to_owned();
+    let mut extension: String = "".to_owned();
+
+    let split = item.split(std::path::MAIN_SEPARATOR).collect::<Vec<&str>>();
+    if split.len() > 2 {
+        component = split[0].to_owned();
+        name = split[1].to_owned();
+        extension = Path::new(split.last().unwrap())
+            .extension()
+            .unwrap_or(OsStr::new(""))
+            .to_str()
+            .unwrap()
+            .to_owned();
+    } else if split.len() > 1 {
+        name = split[0].to_owned();
+        extension = Path::new(split[1])
+            .extension()
+            .unwrap_or(OsStr::new(""))
+            .to_str()
+            .unwrap()
+            .to_owned();
+    } else if !split.is_empty() {
+        name = split[0].to_owned();
+    }
+
+    (component, name, extension)
+}
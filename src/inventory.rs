@@ -0,0 +1,147 @@
+// the skin roots in the Skins folder, which package put them there and which
+// of their configs Rainmeter has loaded
+
+use crate::ini::IniFile;
+use crate::plan;
+use crate::receipts::ReceiptStore;
+use crate::Error;
+use crate::RainmeterSettings;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// A folder of a skin root holding skin files.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkinConfig {
+    /// Root\Folder, the way Rainmeter names it.
+    pub name: String,
+    /// Whether Rainmeter has one of its skins loaded.
+    pub active: bool,
+}
+
+/// A top-level folder of the Skins folder.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkinRoot {
+    /// The folder name.
+    pub name: String,
+    /// None for a root the installer didn't put there.
+    pub package: Option<String>,
+    /// The version of the package that installed it.
+    pub version: Option<String>,
+    /// Every config below the root.
+    pub configs: Vec<SkinConfig>,
+    /// Bytes of every file below the root.
+    pub size: u64,
+}
+
+impl SkinRoot {
+    /// Whether an installed package owns the root.
+    pub fn is_managed(&self) -> bool {
+        self.package.is_some()
+    }
+}
+
+/// Every skin root by name, folders starting with @ are not skins to Rainmeter.
+pub fn skin_roots(settings: &RainmeterSettings) -> Result<Vec<SkinRoot>, Error> {
+    let skins_path = Path::new(&settings.skins_path);
+    let store = ReceiptStore::load(settings)?;
+    let rainmeter_ini = IniFile::read(&Path::new(&settings.settings_path).join("Rainmeter.ini"))?;
+
+    let mut names = vec![];
+    for entry in fs::read_dir(skins_path)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if path.is_dir() && !name.starts_with('@') {
+            names.push(name);
+        }
+    }
+    names.sort_by_key(|name| name.to_lowercase());
+
+    let mut roots = vec![];
+    for name in names {
+        let dir = skins_path.join(&name);
+        let receipt = store.receipts.iter().find(|receipt| {
+            receipt
+                .components
+                .iter()
+                .any(|c| c.component == "Skins" && c.name.eq_ignore_ascii_case(&name))
+        });
+
+        let mut configs = vec![];
+        find_configs(&dir, &name, &mut configs);
+        let configs = configs
+            .into_iter()
+            .map(|config| SkinConfig {
+                active: is_active(&rainmeter_ini, &config),
+                name: config,
+            })
+            .collect();
+
+        let size = plan::list_files(&dir)
+            .iter()
+            .filter_map(|file| fs::metadata(file).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        roots.push(SkinRoot {
+            package: receipt.map(|r| r.name.to_owned()),
+            version: receipt.and_then(|r| r.version.to_owned()),
+            name,
+            configs,
+            size,
+        });
+    }
+    Ok(roots)
+}
+
+// a folder holding .ini files is a config, @Resources and the like are not
+fn find_configs(dir: &Path, name: &str, configs: &mut Vec<String>) {
+    let mut entries: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+
+    let has_skin = entries.iter().any(|path| {
+        path.is_file()
+            && path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("ini"))
+    });
+    if has_skin {
+        configs.push(name.to_owned());
+    }
+    for path in entries.iter().filter(|path| path.is_dir()) {
+        let folder = path.file_name().unwrap().to_string_lossy();
+        if folder.starts_with('@') {
+            continue;
+        }
+        find_configs(path, &format!("{}\\{}", name, folder), configs);
+    }
+}
+
+// Rainmeter.ini has Active=n for a loaded config, n being the skin file, 0 when unloaded
+fn is_active(rainmeter_ini: &IniFile, config: &str) -> bool {
+    match rainmeter_ini.get(config, "Active") {
+        Some(active) => active.trim().parse::<i64>().is_ok_and(|n| n > 0),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ini::Encoding;
+
+    #[test]
+    fn reads_active_configs() {
+        let ini = IniFile::parse(
+            "[Rainmeter]\nSkinPath=C:\\Skins\\\n\n[Suite\\Clock]\nActive=1\n\n[suite\\weather]\nActive=0\n",
+            Encoding::Utf8,
+        );
+        assert!(is_active(&ini, "Suite\\Clock"));
+        assert!(is_active(&ini, "suite\\clock"));
+        assert!(!is_active(&ini, "Suite\\Weather"));
+        assert!(!is_active(&ini, "Suite"));
+    }
+}
@@ -0,0 +1,61 @@
+//! Installs Rainmeter .rmskin packages.
+//!
+//! [`Installer`] reads and checks a package into a [`PreparedInstall`], which
+//! can print an [`InstallPlan`] without touching anything or carry it out.
+//! The `rm_skin_installer` binary is a command-line wrapper around it.
+
+#![warn(missing_docs)]
+
+/// The package's zip archive, read in place.
+pub mod archive;
+/// Backup generations kept in `Skins\@Backup`.
+pub mod backups;
+/// Skin files the user changed since the previous install.
+pub mod conflicts;
+/// The error type of the library.
+pub mod error;
+/// Reading and editing ini files without losing their layout.
+pub mod ini;
+/// Reading, checking and installing a package.
+pub mod installer;
+/// The skin roots in the Skins folder and who installed them.
+pub mod inventory;
+/// The `[rmskin]` section of RMSKIN.ini.
+pub mod manifest;
+/// Rules that follow variables renamed or moved between versions.
+pub mod migrations;
+/// Telling an .rmskin apart from a plain or damaged zip.
+pub mod package;
+/// A minimal reader for the PE images of Rainmeter and its plugins.
+pub mod pe;
+/// Everything an install will do, worked out before anything is written.
+pub mod plan;
+/// Deciding whether a plugin may replace the installed one.
+pub mod plugins;
+/// Closing and starting Rainmeter.
+pub mod rainmeter;
+/// The record of every package installed and the files it wrote.
+pub mod receipts;
+/// The MinimumRainmeter and MinimumWindows checks.
+pub mod requirements;
+/// Checks on entry names before anything is extracted.
+pub mod sanitize;
+/// Where Rainmeter and its settings are installed.
+pub mod settings;
+mod storage;
+/// Removing an installed package.
+pub mod uninstaller;
+/// Carrying the user's values in the VariableFiles across installs.
+pub mod variables;
+/// Checking an installed package against its receipt and repairing it.
+pub mod verify;
+/// Version numbers as Rainmeter, Windows and package authors write them.
+pub mod version;
+
+pub use error::Error;
+pub use installer::Installer;
+pub use installer::PreparedInstall;
+pub use manifest::PackageManifest;
+pub use plan::InstallPlan;
+pub use plan::InstallProgress;
+pub use settings::RainmeterSettings;
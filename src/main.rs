@@ -1,961 +1,554 @@
 #![windows_subsystem = "windows"] // hide console window
 
 use clap::Parser;
-use ini::Ini;
-use ini::ParseOption;
-use std::ffi::OsStr;
-use std::fs;
+use clap::Subcommand;
+use rainmeter_skin_installer::backups;
+use rainmeter_skin_installer::backups::BackupFormat;
+use rainmeter_skin_installer::backups::BackupIndex;
+use rainmeter_skin_installer::backups::BackupRetention;
+use rainmeter_skin_installer::conflicts::ModifiedFilePolicy;
+use rainmeter_skin_installer::inventory;
+use rainmeter_skin_installer::plan::CarriedVariables;
+use rainmeter_skin_installer::receipts::ReceiptStore;
+use rainmeter_skin_installer::uninstaller::Uninstaller;
+use rainmeter_skin_installer::variables::VariablePolicy;
+use rainmeter_skin_installer::verify;
+use rainmeter_skin_installer::InstallPlan;
+use rainmeter_skin_installer::Installer;
+use rainmeter_skin_installer::PreparedInstall;
+use rainmeter_skin_installer::RainmeterSettings;
+use std::io::IsTerminal;
+use std::io::Write;
 use std::path::Path;
-use std::path::PathBuf;
-use std::process::Command;
 use std::process::ExitCode;
-use uuid::Uuid;
-use zip::ZipArchive;
-
-const SHRT_MAX: usize = 32767;
-
-#[derive(Debug)]
-struct RainmeterSettings {
-    skins_path: String,       // %USERPROFILE%\Documents\Rainmeter\Skins
-    application_path: String, // %PROGRAMFILES%\Rainmeter
-    settings_path: String,    // %APPDATA%\Rainmeter
-}
-
-#[derive(Debug)]
-struct InstallOptions {
-    was_running: bool,
-    skinfile: String,
-    temp_dir: String,
-    plugins: Vec<String>,
-    skins: Vec<String>,
-    layouts: Vec<String>,
-    variable_files: Vec<String>,
-    merge_skins: bool,
-    load_type: Option<String>,
-    load: Option<String>,
-}
 
 #[derive(Parser, Debug)]
 #[clap(
     name = "RmSkinInstaller",
     version = "0.0.0.0",
     author = "deathcrafter",
-    long_about = "Command-line Rainmeter skin installer"
+    long_about = "Command-line Rainmeter skin installer",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 struct Opts {
-    #[arg(long)]
-    skin: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    #[arg(long)]
+    #[arg(long, required = true)]
+    skin: Option<String>,
+
+    /// same as --variables=keep
+    #[arg(long, conflicts_with = "variables")]
     keepvariables: bool,
 
+    /// what happens to the user's values in the VariableFiles:
+    /// keep, reset, keep-existing-keys-only or prompt-file, which needs a console
+    #[arg(long, default_value = "keep")]
+    variables: VariablePolicy,
+
+    /// what happens to skin files changed since the previous install:
+    /// side-copy, keep or overwrite
+    #[arg(long, default_value = "side-copy")]
+    modified_files: ModifiedFilePolicy,
+
     #[arg(long)]
     nobackup: bool,
+
+    /// backup generations kept for each skin and addon, 0 keeps all
+    #[arg(long, default_value_t = 5)]
+    backup_keep: usize,
+
+    /// delete backups older than this many days
+    #[arg(long)]
+    backup_max_age: Option<u64>,
+
+    /// how backups are written: folder, or zip to compress them and keep the
+    /// replaced layouts, plugins and Rainmeter.ini sections with the skin
+    #[arg(long, default_value = "folder")]
+    backup_format: BackupFormat,
+
+    /// install even if the package's MinimumRainmeter or MinimumWindows is not met
+    #[arg(long)]
+    ignore_requirements: bool,
+
+    /// replace installed plugins even if the package ships an older version
+    #[arg(long)]
+    force_plugins: bool,
+
+    /// allow installing the package's addons, which are executables
+    #[arg(long)]
+    allow_addons: bool,
+
+    /// install even if the installed package has a newer version
+    #[arg(long)]
+    allow_downgrade: bool,
+
+    /// print everything the install would do without changing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// sections of the variable files to carry over, comma separated, * keeps all
+    #[arg(long, value_delimiter = ',')]
+    variable_sections: Option<Vec<String>>,
+
+    /// write the variable preservation report to this file as JSON
+    #[arg(long)]
+    variable_report: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// remove an installed package, plugins other skins still use are kept
+    Uninstall {
+        /// the package name it was installed under
+        package: String,
+
+        /// copy the package's variable files to Skins\@Backup before removing them
+        #[arg(long)]
+        backup_variables: bool,
+
+        /// print what would be removed without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// never replace or uninstall an installed package
+    Pin { package: String },
+    /// allow replacing or uninstalling a pinned package again
+    Unpin { package: String },
+    /// check the files of an installed package against its receipt
+    Verify {
+        package: String,
+
+        /// print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// restore the missing and modified files of an installed package, variable files are left alone
+    Repair {
+        package: String,
+
+        /// the .rmskin the package was installed from
+        #[arg(long)]
+        from: String,
+    },
+    /// manage the backups in Skins\@Backup
+    Backups {
+        #[command(subcommand)]
+        command: BackupsCommand,
+    },
+    /// list the skin roots, the package that installed each and its configs
+    List {
+        /// print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BackupsCommand {
+    /// list every backup generation and the space it uses
+    List {
+        /// print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 fn main() -> ExitCode {
     let opts = Opts::parse();
 
-    if !Path::new(&opts.skin).is_file() {
+    match &opts.command {
+        Some(Command::Uninstall {
+            package,
+            backup_variables,
+            dry_run,
+        }) => return uninstall(package, *backup_variables, *dry_run),
+        Some(Command::List { json }) => return list(*json),
+        Some(Command::Backups {
+            command: BackupsCommand::List { json },
+        }) => return list_backups(*json),
+        Some(Command::Verify { package, json }) => return verify(package, *json),
+        Some(Command::Repair { package, from }) => return repair(package, from),
+        Some(Command::Pin { package }) => return pin(package, true),
+        Some(Command::Unpin { package }) => return pin(package, false),
+        None => (),
+    }
+
+    let skin = opts.skin.as_deref().unwrap_or_default();
+    if !Path::new(skin).is_file() {
         eprintln!("Skin file not found.");
         return ExitCode::FAILURE;
     }
 
-    let mut install_options: InstallOptions = InstallOptions {
-        was_running: false,
-        skinfile: opts.skin.clone(),
-        temp_dir: std::env::temp_dir().as_path().to_str().unwrap().to_owned()
-            + Uuid::new_v4().to_string().as_str(),
-        plugins: vec![],
-        skins: vec![],
-        layouts: vec![],
-        variable_files: vec![],
-        merge_skins: false,
-        load_type: None,
-        load: None,
-    };
-
-    // only support standard rainmeter installation
-    let mut rainmeter_settings: RainmeterSettings = RainmeterSettings {
-        skins_path: "".to_owned(),
-        application_path: std::env::var("PROGRAMFILES").unwrap_or("".to_owned()) + "\\Rainmeter\\",
-        settings_path: std::env::var("APPDATA").unwrap_or("".to_owned()) + "\\Rainmeter\\",
-    };
-    if !Path::new(&rainmeter_settings.application_path).exists()
-        || !Path::new(&rainmeter_settings.settings_path)
-            .join("Rainmeter.ini")
-            .exists()
-    {
-        eprintln!("Rainmeter not installed or not run for the first time.");
+    // without a console stdin reads nothing and every file would silently be kept
+    if opts.variables == VariablePolicy::PromptFile && !std::io::stdin().is_terminal() {
+        eprintln!("--variables=prompt-file needs a console to answer in, run it from a terminal.");
         return ExitCode::FAILURE;
     }
 
     println!("Reading Rainmeter settings...");
-    match read_rainmeter_settings(&mut rainmeter_settings) {
-        Ok(_) => (),
+    let rainmeter_settings = match RainmeterSettings::detect() {
+        Ok(settings) => settings,
         Err(e) => {
-            eprintln!("Error reading Rainmeter settings: {}", e);
-            return ExitCode::FAILURE;
-        }
-    }
-
-    println!("Extracting skin to: {}", install_options.temp_dir);
-    match extract_zip(&mut install_options) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error extracting skin: {}", e);
+            eprintln!("Error: {}", e);
             return ExitCode::FAILURE;
         }
     };
 
-    println!("Reading skin options...");
-    match read_options(&mut install_options) {
-        Ok(_) => (),
+    println!("Reading skin package: {}", skin);
+    let mut installer = Installer::new(skin)
+        .settings(rainmeter_settings)
+        .variable_policy(if opts.keepvariables {
+            VariablePolicy::Keep
+        } else {
+            opts.variables
+        })
+        .modified_files(opts.modified_files)
+        .backup(!opts.nobackup)
+        .backup_retention(BackupRetention {
+            count: match opts.backup_keep {
+                0 => None,
+                count => Some(count),
+            },
+            max_age_days: opts.backup_max_age,
+        })
+        .backup_format(opts.backup_format)
+        .ignore_requirements(opts.ignore_requirements)
+        .force_plugins(opts.force_plugins)
+        .allow_addons(opts.allow_addons)
+        .allow_downgrade(opts.allow_downgrade);
+    if let Some(sections) = &opts.variable_sections {
+        installer = installer.variable_sections(sections.clone());
+    }
+    let mut prepared = match installer.open() {
+        Ok(prepared) => prepared,
         Err(e) => {
-            eprintln!("Error reading options: {}", e);
+            eprintln!("Error: {}", e);
             return ExitCode::FAILURE;
         }
     };
-
-    // close rainmeter if running to start processing files
-    println!("Closing Rainmeter if active...");
-    if !close_rainmeter_if_running(&mut install_options.was_running) {
-        eprintln!("Rainmeter is running. Please close Rainmeter before installing.");
-        return ExitCode::FAILURE;
+    println!("Rainmeter architecture: {}", prepared.plugin_machine());
+    for warning in prepared.warnings() {
+        println!("Warning: {}", warning);
     }
 
-    println!("Installing plugins...");
-    match move_plugins(&mut install_options, &mut rainmeter_settings) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error installing plugins: {}", e);
+    if opts.dry_run {
+        let plan = match prepared.plan() {
+            Ok(plan) => plan,
+            Err(e) => {
+                eprintln!("Error planning install: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        println!("{}", plan);
+        for warning in &plan.warnings {
+            println!("Warning: {}", warning);
+        }
+        if !write_variable_report(&opts, &plan) {
             return ExitCode::FAILURE;
         }
-    };
+        println!("Dry run, nothing was changed.");
+        return ExitCode::SUCCESS;
+    }
 
-    println!("Installing layouts...");
-    match move_layouts(&mut install_options, &mut rainmeter_settings) {
-        Ok(_) => (),
+    println!("Variables policy: {}", prepared.variable_policy());
+    println!("Installing...");
+    let plan = match prepared.install_with(prompt_file, |progress| println!("{}", progress)) {
+        Ok(plan) => plan,
         Err(e) => {
-            eprintln!("Error installing layouts: {}", e);
+            eprintln!("Error installing package: {}", e);
             return ExitCode::FAILURE;
         }
     };
-
-    if install_options.merge_skins {
-        println!("Merging skins...");
-
-        if opts.keepvariables {
-            println!("Keeping variables...");
-            match keep_variables(&mut install_options, &mut rainmeter_settings) {
-                Ok(_) => (),
-                Err(e) => {
-                    eprintln!("Error keeping variables: {}", e);
-                    return ExitCode::FAILURE;
-                }
-            };
+    for warning in &plan.warnings {
+        println!("Warning: {}", warning);
+    }
+    for decision in &plan.plugins {
+        println!("  {}", decision);
+    }
+    if !plan.variable_files.is_empty() {
+        println!("VariableFiles:");
+        for pattern in &plan.variable_files {
+            println!("  {}", pattern);
         }
+    }
+    println!("{}", plan.summary());
 
-        match merge_skins(&mut install_options, &mut rainmeter_settings) {
-            Ok(_) => (),
-            Err(e) => {
-                eprintln!("Error merging skins: {}", e);
-                return ExitCode::FAILURE;
-            }
-        };
-    } else {
-        println!("Restoring variables...");
-        match keep_variables(&mut install_options, &mut rainmeter_settings) {
-            Ok(_) => (),
-            Err(e) => {
-                eprintln!("Error keeping variables: {}", e);
-                return ExitCode::FAILURE;
-            }
-        };
+    print_report(&prepared);
 
-        if !opts.nobackup {
-            println!("Creating backup...");
-            match create_backup(&mut install_options, &mut rainmeter_settings) {
-                Ok(_) => (),
-                Err(e) => {
-                    eprintln!("Error creating backup: {}", e);
-                    return ExitCode::FAILURE;
-                }
-            };
+    if !plan.conflicts.is_empty() {
+        println!("Modified files:");
+        for conflict in &plan.conflicts {
+            println!("  {}", conflict);
         }
-
-        println!("Installing skins...");
-        match move_skins(&mut install_options, &mut rainmeter_settings) {
-            Ok(_) => (),
-            Err(e) => {
-                eprintln!("Error installing skins: {}", e);
-                return ExitCode::FAILURE;
-            }
-        };
     }
-
-    start_rainmeter(&mut install_options, &mut rainmeter_settings);
-
-    // cleanup
-    println!("Cleaning up...");
-    match fs::remove_dir_all(Path::new(&install_options.temp_dir)) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error cleaning up: {}", e);
-            return ExitCode::FAILURE;
+    if !plan.migrations.is_empty() {
+        println!("Migrations applied:");
+        for migration in &plan.migrations {
+            println!("  {}", migration);
         }
-    };
+    }
+    if !plan.variables.is_empty() {
+        println!("Variables:");
+        for report in plan.variable_reports() {
+            println!("  {}", report);
+        }
+    }
+    if !write_variable_report(&opts, &plan) {
+        return ExitCode::FAILURE;
+    }
 
-    return ExitCode::SUCCESS;
+    ExitCode::SUCCESS
 }
 
-// region Rainmeter process handler
-
-fn close_rainmeter_if_running(was_running: &mut bool) -> bool {
-    unsafe {
-        let hwnd = windows::Win32::UI::WindowsAndMessaging::FindWindowW(
-            windows::w!("DummyRainWClass"),
-            windows::w!("Rainmeter control window"),
-        );
-
-        if hwnd == windows::Win32::Foundation::HWND(0) {
-            return true;
-        };
-
-        *was_running = true;
-
-        let mut process_id: u32 = 0;
-
-        let _thread_id = windows::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId(
-            hwnd,
-            Some(&mut process_id as *mut u32),
-        );
+fn uninstall(package: &str, backup_variables: bool, dry_run: bool) -> ExitCode {
+    let uninstaller = Uninstaller::new(package).backup_variables(backup_variables);
 
-        match windows::Win32::System::Threading::OpenProcess(
-            windows::Win32::System::Threading::PROCESS_TERMINATE
-                | windows::Win32::System::Threading::PROCESS_SYNCHRONIZE,
-            false,
-            process_id,
-        ) {
-            Ok(process_handle) => {
-                windows::Win32::UI::WindowsAndMessaging::PostMessageW(
-                    hwnd,
-                    windows::Win32::UI::WindowsAndMessaging::WM_DESTROY,
-                    windows::Win32::Foundation::WPARAM(0),
-                    windows::Win32::Foundation::LPARAM(0),
-                );
-
-                windows::Win32::System::Threading::WaitForSingleObject(process_handle, 5000);
-                let mut lpexitcode: u32 = 0;
-                windows::Win32::System::Threading::GetExitCodeProcess(
-                    process_handle,
-                    &mut lpexitcode as *mut u32,
-                );
-                windows::Win32::Foundation::CloseHandle(process_handle);
-
-                if lpexitcode == 259u32 {
-                    return false;
-                }
-
-                return true;
+    if dry_run {
+        return match uninstaller.plan() {
+            Ok(plan) => {
+                println!("{}", plan);
+                println!("Dry run, nothing was changed.");
+                ExitCode::SUCCESS
             }
             Err(e) => {
-                println!("Error opening Rainmeter process: {}", e);
-                return false;
+                eprintln!("Error planning uninstall: {}", e);
+                ExitCode::FAILURE
             }
-        }
+        };
     }
-}
-
-fn start_rainmeter(
-    install_options: &mut InstallOptions,
-    rainmeter_settings: &mut RainmeterSettings,
-) {
-    let mut rainmeter_exe = rainmeter_settings.application_path.clone();
-    rainmeter_exe.push_str("Rainmeter.exe");
 
-    let mut command = Command::new("powershell");
-    command.arg("Start-Process");
-    command.arg("\"".to_owned() + &rainmeter_exe + "\"");
-
-    let mut child = match command.spawn() {
-        Ok(child) => child,
-        Err(e) => {
-            eprintln!("Error starting Rainmeter: {}", e);
-            return;
+    println!("Uninstalling {}...", package);
+    match uninstaller.uninstall() {
+        Ok(plan) => {
+            println!("{}", plan);
+            for warning in &plan.warnings {
+                println!("Warning: {}", warning);
+            }
+            ExitCode::SUCCESS
         }
-    };
-
-    match child.wait() {
-        Ok(_) => (),
         Err(e) => {
-            eprintln!("Error waiting for Rainmeter to start: {}", e);
-            return;
+            eprintln!("Error uninstalling package: {}", e);
+            ExitCode::FAILURE
         }
-    };
-
-    std::thread::sleep(std::time::Duration::from_millis(1000)); // wait for a for rainmeter to start
-
-    if install_options.load_type == None {
-        return;
     }
-
-    command.arg("-ArgumentList");
-    if install_options.load_type.as_ref().unwrap() == "Skin" {
-        let skin = install_options.load.as_mut().unwrap().rfind('\\').unwrap();
-        let (skin, file) = install_options.load.as_mut().unwrap().split_at(skin + 1);
-        command.arg("@('[!ActivateConfig \"".to_owned() + skin + "\" \"" + file + "\"]')");
-    } else if install_options.load_type.as_ref().unwrap() == "Layout" {
-        command.arg(
-            "@('[!LoadLayout \"".to_owned()
-                + install_options.load.as_mut().unwrap().as_str()
-                + "\"]')",
-        );
-    }
-
-    let mut child = match command.spawn() {
-        Ok(child) => child,
-        Err(e) => {
-            eprintln!("Error starting Rainmeter with commands: {}", e);
-            return;
-        }
-    };
-
-    match child.wait() {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error waiting for Rainmeter to start with commands: {}", e);
-            return;
-        }
-    };
 }
 
-// endregion
-
-// region config parsers
-
-fn read_rainmeter_settings(
-    rainmeter_settings: &mut RainmeterSettings,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let settings_filepath = Path::new(rainmeter_settings.settings_path.as_str())
-        .join("Rainmeter.ini")
-        .to_str()
-        .unwrap()
-        .to_owned();
-
-    let settings = match read_ini(settings_filepath.as_str()) {
-        Ok(ini) => ini,
+fn verify(package: &str, json: bool) -> ExitCode {
+    let report = match RainmeterSettings::detect().and_then(|s| verify::verify(&s, package)) {
+        Ok(report) => report,
         Err(e) => {
-            println!("Error opening settings file: {}", e);
-            return Err(Box::new(e));
-        }
-    };
-
-    let rainmeter = settings.section(Some("Rainmeter")).unwrap();
-
-    rainmeter_settings.skins_path = match rainmeter.get("SkinPath") {
-        Some(skin_path) => skin_path.to_owned(),
-        None => {
-            std::env::var("USERPROFILE").unwrap_or("".to_owned())
-                + "\\Documents\\Rainmeter\\Skins\\"
+            eprintln!("Error verifying package: {}", e);
+            return ExitCode::FAILURE;
         }
     };
 
-    if !Path::new(rainmeter_settings.skins_path.as_str()).is_dir() {
-        if fs::create_dir_all(rainmeter_settings.skins_path.as_str()).is_err() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "Rainmeter skins folder not found and could not be created.",
-            )));
-        }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        println!("{}", report);
+    }
+    if report.is_intact() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
     }
-
-    Ok(())
-}
-
-fn read_options(install_options: &mut InstallOptions) -> Result<(), Box<dyn std::error::Error>> {
-    let settings_filepath = Path::new(install_options.temp_dir.as_str()).join("RMSKIN.ini");
-
-    let settings = match read_ini(settings_filepath.to_str().unwrap()) {
-        Ok(ini) => ini,
-        Err(_) => {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "RMSKIN.ini not found.",
-            )));
-        }
-    };
-
-    let rmskin = settings.section(Some("rmskin")).unwrap();
-
-    install_options.load_type = match rmskin.get("LoadType") {
-        Some(load_type) => Some(load_type.to_owned()),
-        None => None,
-    };
-    install_options.load = match rmskin.get("Load") {
-        Some(load) => Some(load.to_owned()),
-        None => None,
-    };
-    install_options.variable_files = match rmskin.get("VariableFiles") {
-        Some(skins) => skins.split(" | ").map(|s| s.to_owned()).collect(),
-        None => vec![],
-    };
-    install_options.merge_skins = match rmskin.get("MergeSkins") {
-        Some(merge_skins) => merge_skins == "1",
-        None => false,
-    };
-
-    Ok(())
 }
 
-// endregion
-
-// #region file operations
-
-fn extract_zip(install_options: &mut InstallOptions) -> Result<(), Box<dyn std::error::Error>> {
-    let zip = install_options.skinfile.as_str();
-
-    // if the temppath exists, delete it
-    if Path::new(install_options.temp_dir.as_str()).is_dir() {
-        if (fs::remove_dir_all(Path::new(install_options.temp_dir.as_str()))).is_err() {
-            println!("Error removing directory");
-        }
-    }
-    if fs::DirBuilder::new()
-        .recursive(true)
-        .create(Path::new(install_options.temp_dir.as_str()))
-        .is_err()
+fn repair(package: &str, from: &str) -> ExitCode {
+    println!("Repairing {} from {}...", package, from);
+    let mut warnings = vec![];
+    let repaired = match RainmeterSettings::detect()
+        .and_then(|s| verify::repair(&s, package, Path::new(from), &mut warnings))
     {
-        println!(
-            "Error creating directory: {}",
-            install_options.temp_dir.as_str()
-        );
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Error creating directory",
-        )));
-    }
-
-    let zip_path: &Path = Path::new(zip);
-    let zip_file: fs::File = match fs::File::open(zip_path) {
-        Ok(file) => file,
-        Err(e) => {
-            println!("Error opening zip file: {}", e);
-            return Err(Box::new(e));
-        }
-    };
-
-    let mut archive: ZipArchive<fs::File> = match ZipArchive::new(zip_file) {
-        Ok(archive) => archive,
+        Ok(repaired) => repaired,
         Err(e) => {
-            println!("Error reading zip file: {}", e);
-            return Err(Box::new(e));
+            eprintln!("Error repairing package: {}", e);
+            return ExitCode::FAILURE;
         }
     };
 
-    let outpath = PathBuf::from(install_options.temp_dir.as_str());
-
-    let mut found_rmskin = false;
-
-    // iterate over the contents of the zip file
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i).unwrap();
-
-        let mut outfilename: PathBuf = match file.enclosed_name() {
-            Some(path) => path.to_owned(),
-            None => continue,
-        };
-
-        let (component, name, extension) = parse_zip_item(&outfilename.to_str().unwrap());
-        if component.eq("Skins") {
-            if !name.is_empty() {
-                install_options.skins.push(name.to_owned());
-            }
-        }
-        if component.eq("Layouts") {
-            if !name.is_empty() {
-                install_options.layouts.push(name.to_owned());
-            }
-        }
-        if component.eq("Plugins") {
-            if name.eq("64bit") && extension.eq("dll") {
-                let plugin_name = outfilename
-                    .to_str()
-                    .unwrap()
-                    .split(std::path::MAIN_SEPARATOR)
-                    .last()
-                    .unwrap()
-                    .to_owned();
-                install_options.plugins.push(plugin_name);
-            } else {
-                // don't process 32bit plugins
-                continue;
-            }
-        }
-        if name.eq("RMSKIN.ini") {
-            found_rmskin = true;
-        }
-
-        outfilename = outpath.join(outfilename);
-
-        if outfilename
-            .to_str()
-            .unwrap()
-            .ends_with(std::path::MAIN_SEPARATOR.to_string().as_str())
-        {
-            match fs::DirBuilder::new().recursive(true).create(outfilename) {
-                Ok(_) => (),
-                Err(e) => {
-                    println!("Error creating directory: {}", e);
-                    return Err(Box::new(e));
-                }
-            };
-            continue;
-        }
-
-        match fs::DirBuilder::new()
-            .recursive(true)
-            .create(outfilename.parent().unwrap())
-        {
-            Ok(_) => (),
-            Err(e) => {
-                println!("Error creating directory: {}", e);
-                return Err(Box::new(e));
-            }
-        };
-
-        let mut outfile = match fs::File::create(&outfilename) {
-            Ok(file) => file,
-            Err(e) => {
-                println!("Error creating file: {}", e);
-                return Err(Box::new(e));
-            }
-        };
-
-        match std::io::copy(&mut file, &mut outfile) {
-            Ok(_) => (),
-            Err(e) => {
-                println!("Error extracting file: {}", outfilename.to_str().unwrap());
-                return Err(Box::new(e));
-            }
-        };
+    for warning in &warnings {
+        println!("Warning: {}", warning);
     }
-
-    if !found_rmskin {
-        println!("Error: RMSKIN.ini not found in zip");
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "RMSKIN.ini not found in zip",
-        )));
+    if repaired.is_empty() {
+        println!("Nothing to repair.");
     }
-
-    install_options.skins.sort();
-    install_options.skins.dedup();
-    install_options.layouts.sort();
-    install_options.layouts.dedup();
-    install_options.plugins.sort();
-    install_options.plugins.dedup();
-
-    return Ok(());
+    for file in &repaired {
+        println!("  {}", file);
+    }
+    if repaired.iter().any(|file| file.error.is_some()) {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
 }
 
-fn keep_variables(
-    install_options: &mut InstallOptions,
-    rainmeter_settings: &mut RainmeterSettings,
-) -> Result<(), Box<dyn std::error::Error>> {
-    for varfile in &install_options.variable_files[..] {
-        let oldfile = Path::new(&rainmeter_settings.skins_path).join(Path::new(&varfile));
-        let newfile = Path::new(&install_options.temp_dir)
-            .join("Skins")
-            .join(Path::new(&varfile));
-
-        if !oldfile.is_file() {
-            continue;
-        }
-
-        if !newfile.is_file() {
-            if fs::File::create(&newfile).is_err() {
-                continue;
+fn pin(package: &str, pinned: bool) -> ExitCode {
+    let result = RainmeterSettings::detect().and_then(|settings| {
+        let mut store = ReceiptStore::load(&settings)?;
+        store.set_pinned(package, pinned)?;
+        store.save(&settings)
+    });
+    match result {
+        Ok(_) => {
+            if pinned {
+                println!("Pinned {}, it won't be replaced.", package);
+            } else {
+                println!("Unpinned {}.", package);
             }
+            ExitCode::SUCCESS
         }
-
-        let mut keys: Vec<Vec<u16>> = vec![];
-        let mut values: Vec<Vec<u16>> = vec![];
-
-        read_win_ini(&oldfile, &mut keys, &mut values);
-
-        unsafe {
-            let appname = windows::core::HSTRING::from("Variables");
-            let filename = windows::core::HSTRING::from(newfile.to_str().unwrap());
-            let mut i = 0;
-            while i < keys.len() {
-                let key = windows::core::PCWSTR(keys[i].as_ptr());
-                let value = windows::core::PCWSTR(values[i].as_ptr());
-                windows::Win32::System::WindowsProgramming::WritePrivateProfileStringW(
-                    &appname, key, value, &filename,
-                );
-                i += 1;
-            }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
         }
     }
-
-    Ok(())
 }
 
-// todo: add version check
-fn move_plugins(
-    install_options: &mut InstallOptions,
-    rainmeter_settings: &mut RainmeterSettings,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let oldfile = Path::new(&install_options.temp_dir).join("Plugins\\64bit");
-    let newfile = Path::new(&rainmeter_settings.settings_path).join("Plugins");
-
-    copy_dir_all(&oldfile, &newfile)?;
-    Ok(())
-}
-
-fn move_layouts(
-    install_options: &mut InstallOptions,
-    rainmeter_settings: &mut RainmeterSettings,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let oldfile = Path::new(&install_options.temp_dir).join("Layouts");
-    let newfile = Path::new(&rainmeter_settings.settings_path).join("Layouts");
-
-    copy_dir_all(&oldfile, &newfile)?;
-    Ok(())
-}
-
-fn create_backup(
-    install_options: &mut InstallOptions,
-    rainmeter_settings: &mut RainmeterSettings,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let backup_dir = Path::new(&rainmeter_settings.skins_path).join("@Backup");
-    if !backup_dir.is_dir() {
-        match fs::create_dir_all(&backup_dir) {
-            Ok(_) => (),
-            Err(e) => {
-                println!("Error creating backup directory: {}", e);
-                return Err(Box::new(e));
-            }
-        }
-    }
-    for skin in &install_options.skins[..] {
-        let oldfile = Path::new(&rainmeter_settings.skins_path).join(Path::new(&skin));
-        if !oldfile.is_dir() {
-            continue;
-        }
-
-        let newfile = Path::new(&backup_dir).join(Path::new(&skin));
-
-        if newfile.is_dir() {
-            match fs::remove_dir_all(&newfile) {
-                Ok(_) => (),
-                Err(e) => {
-                    println!("Error removing directory: {}", newfile.to_str().unwrap());
-                    return Err(Box::new(e));
-                }
-            }
+fn list(json: bool) -> ExitCode {
+    let roots = match RainmeterSettings::detect().and_then(|s| inventory::skin_roots(&s)) {
+        Ok(roots) => roots,
+        Err(e) => {
+            eprintln!("Error listing skins: {}", e);
+            return ExitCode::FAILURE;
         }
+    };
 
-        match copy_dir_all(&oldfile, &newfile) {
-            Ok(_) => (),
-            Err(e) => {
-                println!("Error moving skin to backup: {}", oldfile.to_str().unwrap());
-                return Err(e);
-            }
-        };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&roots).unwrap());
+        return ExitCode::SUCCESS;
+    }
 
-        if oldfile.is_dir() {
-            match fs::remove_dir_all(&oldfile) {
-                Ok(_) => (),
-                Err(e) => {
-                    println!("Error removing directory: {}", oldfile.to_str().unwrap());
-                    return Err(Box::new(e));
-                }
-            }
+    println!(
+        "{:<24} {:<24} {:<12} {:>10}  CONFIGS",
+        "ROOT", "PACKAGE", "VERSION", "SIZE"
+    );
+    for root in &roots {
+        let active = root.configs.iter().filter(|c| c.active).count();
+        println!(
+            "{:<24} {:<24} {:<12} {:>10}  {} ({} active)",
+            root.name,
+            root.package.as_deref().unwrap_or("unmanaged"),
+            root.version.as_deref().unwrap_or("-"),
+            format_size(root.size),
+            root.configs.len(),
+            active
+        );
+        for config in &root.configs {
+            println!(
+                "  {} {}",
+                if config.active { "*" } else { " " },
+                config.name
+            );
         }
     }
 
-    Ok(())
+    ExitCode::SUCCESS
 }
 
-fn move_skins(
-    install_options: &mut InstallOptions,
-    rainmeter_settings: &mut RainmeterSettings,
-) -> Result<(), Box<dyn std::error::Error>> {
-    for skin in &install_options.skins[..] {
-        let oldfile = Path::new(&install_options.temp_dir)
-            .join("Skins")
-            .join(Path::new(&skin));
-        let newfile = Path::new(&rainmeter_settings.skins_path).join(Path::new(&(skin.to_owned())));
-
-        match copy_dir_all(&oldfile, &newfile) {
-            Ok(_) => (),
-            Err(e) => {
-                return Err(e);
-            }
+fn list_backups(json: bool) -> ExitCode {
+    let index = match RainmeterSettings::detect().and_then(|s| BackupIndex::load(&s)) {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("Error listing backups: {}", e);
+            return ExitCode::FAILURE;
         }
-    }
-    Ok(())
-}
+    };
 
-fn merge_skins(
-    install_options: &mut InstallOptions,
-    rainmeter_settings: &mut RainmeterSettings,
-) -> Result<(), Box<dyn std::error::Error>> {
-    for skin in &install_options.skins[..] {
-        let newfile = Path::new(&rainmeter_settings.skins_path).join(Path::new(&skin));
-        let oldfile = Path::new(&install_options.temp_dir)
-            .join("Skins")
-            .join(Path::new(&skin));
-
-        match copy_dir_all(&oldfile, &newfile) {
-            Ok(_) => (),
-            Err(e) => {
-                println!("Error merging file: {}", oldfile.to_str().unwrap());
-                return Err(e);
-            }
-        };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&index.backups).unwrap());
+        return ExitCode::SUCCESS;
     }
-    Ok(())
-}
-
-// #endregion
-
-// #region helper functions
-
-fn parse_zip_item(item: &str) -> (String, String, String) {
-    let mut component: String = "".to_owned();
-    let mut name: String = "".to_owned();
-    let mut extension: String = "".to_owned();
-
-    let split = item.split(std::path::MAIN_SEPARATOR).collect::<Vec<&str>>();
-    if split.len() > 2 {
-        component = split[0].to_owned();
-        name = split[1].to_owned();
-        extension = Path::new(split.last().unwrap())
-            .extension()
-            .unwrap_or(OsStr::new(""))
-            .to_str()
-            .unwrap()
-            .to_owned();
-    } else if split.len() > 1 {
-        name = split[0].to_owned();
-        extension = Path::new(split[1])
-            .extension()
-            .unwrap_or(OsStr::new(""))
-            .to_str()
-            .unwrap()
-            .to_owned();
-    } else if split.len() > 0 {
-        name = split[0].to_owned();
-    }
-
-    return (component, name, extension);
-}
 
-// uses the GetPrivateProfileSectionW function to read a win ini file
-// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-getprivateprofilesectionw
-// reads the ini key and values to u16 vectors, since rust conversions are lossy
-fn read_win_ini(file_path: &Path, keys: &mut Vec<Vec<u16>>, values: &mut Vec<Vec<u16>>) {
-    unsafe {
-        let appname = windows::core::HSTRING::from("Variables");
-        let mut section = vec![0u16; SHRT_MAX];
-        let filename = windows::core::HSTRING::from(file_path.to_str().unwrap());
-
-        windows::Win32::System::WindowsProgramming::GetPrivateProfileSectionW(
-            &appname,
-            Some(&mut section),
-            &filename,
+    let mut entries: Vec<_> = index.backups.iter().collect();
+    entries.sort_by(|a, b| {
+        (a.component.as_str(), a.name.to_lowercase(), b.created).cmp(&(
+            b.component.as_str(),
+            b.name.to_lowercase(),
+            a.created,
+        ))
+    });
+    println!(
+        "{:<8} {:<24} {:<16} {:<12} {:>10}  PATH",
+        "TYPE", "NAME", "CREATED (UTC)", "VERSION", "SIZE"
+    );
+    for backup in entries {
+        println!(
+            "{:<8} {:<24} {:<16} {:<12} {:>10}  {}",
+            backup.component,
+            backup.name,
+            backups::timestamp(backup.created),
+            backup.version.as_deref().unwrap_or("-"),
+            format_size(backup.size),
+            backup.path.display()
         );
-
-        let mut start = 0;
-        let mut end = 0;
-        let mut was_null = false;
-        for c in section.iter() {
-            if std::char::from_u32(*c as u32) == Some('=') {
-                was_null = false;
-                keys.push(section[start..end].to_vec());
-                keys.last_mut().unwrap().push(0);
-                end = end + 1;
-                start = end;
-            } else if std::char::from_u32(*c as u32) == Some('\0') {
-                if was_null {
-                    break;
-                }
-                was_null = true;
-                values.push(section[start..(end + 1)].to_vec());
-                end = end + 1;
-                start = end;
-            } else {
-                was_null = false;
-                end += 1;
-            }
-        }
     }
-}
+    println!(
+        "{} backups, {}",
+        index.backups.len(),
+        format_size(index.total_size())
+    );
 
-fn read_ini(file_path: &str) -> Result<Ini, Box<ini::ParseError>> {
-    let ini_path = Path::new(file_path);
-    if !ini_path.is_file() {
-        eprintln!("Error: {} is not a file", file_path);
-        return Err(Box::new(ini::ParseError {
-            line: 430,
-            col: 0,
-            msg: "Not a file".to_owned(),
-        }));
-    }
-
-    // try utf-8 first
-    return match Ini::load_from_file_opt(
-        ini_path,
-        ParseOption {
-            enabled_quote: false,
-            enabled_escape: false,
-        },
-    ) {
-        Ok(ini) => Ok(ini),
-        // if utf-8 fails, try utf-16
-        Err(e) => {
-            let content = utf16_reader::read_to_string(std::io::BufReader::new(
-                match std::fs::File::open(file_path) {
-                    Ok(file) => file,
-                    Err(_) => {
-                        eprintln!("Error opening ini file: {}", e);
-                        return Err(Box::new(ini::ParseError {
-                            line: 452,
-                            col: 13,
-                            msg: "Error opening file".to_owned(),
-                        }));
-                    }
-                },
-            ));
-            match Ini::load_from_str_opt(
-                &content,
-                ParseOption {
-                    enabled_quote: false,
-                    enabled_escape: false,
-                },
-            ) {
-                Ok(ini) => Ok(ini),
-                Err(e) => {
-                    eprintln!("Error parsing ini file: {}", e);
-                    return Err(Box::new(e));
-                }
-            }
-        }
-    };
+    ExitCode::SUCCESS
 }
 
-fn copy_dir_all(src: &Path, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    if !src.is_dir() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Source is not a directory",
-        )));
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
     }
-
-    if dest.is_file() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Destination is a file",
-        )));
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
     }
+}
 
-    if !dest.is_dir() {
-        match fs::create_dir_all(dest) {
-            Ok(_) => (),
-            Err(e) => {
-                return Err(Box::new(e));
-            }
+fn print_report(prepared: &PreparedInstall) {
+    let components = [
+        ("Skins", prepared.skins()),
+        ("Layouts", prepared.layouts()),
+        ("Plugins", prepared.plugins()),
+        ("Addons", prepared.addons()),
+    ];
+
+    println!("Installed:");
+    for (component, items) in components {
+        if items.is_empty() {
+            continue;
         }
+        println!("  {}: {}", component, items.join(", "));
     }
+}
 
-    for entry in fs::read_dir(src).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        let dest_path = dest.join(path.file_name().unwrap());
-        if path.is_dir() {
-            match copy_dir_all(&path, &dest_path) {
-                Ok(_) => (),
-                Err(e) => {
-                    return Err(e);
-                }
-            }
-        } else {
-            if !dest_path.parent().unwrap().is_dir() {
-                match fs::create_dir_all(&dest_path) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        return Err(Box::new(e));
-                    }
-                }
-            }
-            match fs::copy(&path, &dest_path) {
-                Ok(_) => (),
-                Err(e) => {
-                    return Err(Box::new(e));
-                } // ignore copy errors
-            }
+fn write_variable_report(opts: &Opts, plan: &InstallPlan) -> bool {
+    let path = match &opts.variable_report {
+        Some(path) => path,
+        None => return true,
+    };
+    match std::fs::write(path, plan.variable_report_json()) {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("Error writing variable report {}: {}", path, e);
+            false
         }
     }
-
-    Ok(())
 }
 
-fn _move_dir_all(src: &Path, dest: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    if !src.is_dir() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Source is not a directory",
-        )));
-    }
-
-    if dest.is_file() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Destination is a file",
-        )));
-    }
-
-    if !dest.is_dir() {
-        match fs::create_dir_all(dest) {
-            Ok(_) => (),
-            Err(e) => {
-                return Err(Box::new(e));
-            }
-        }
+fn prompt_file(variables: &CarriedVariables) -> bool {
+    for carried in &variables.sections {
+        println!("  {}", carried.report);
     }
+    print!("Keep your values in {}? [Y/n] ", variables.file.display());
+    let _ = std::io::stdout().flush();
 
-    for entry in fs::read_dir(&src).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        let dest_path = dest.join(path.file_name().unwrap());
-        if path.is_dir() {
-            match _move_dir_all(&path, &dest_path) {
-                Ok(_) => (),
-                Err(e) => {
-                    return Err(e);
-                }
-            }
-        } else {
-            if !dest_path.parent().unwrap().is_dir() {
-                match fs::create_dir_all(&dest_path) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        return Err(Box::new(e));
-                    }
-                }
-            }
-            match fs::rename(&path, &dest_path) {
-                Ok(_) => (),
-                Err(e) => {
-                    return Err(Box::new(e));
-                }
-            }
-        }
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return true;
     }
-    Ok(())
+    !answer.trim().eq_ignore_ascii_case("n") && !answer.trim().eq_ignore_ascii_case("no")
 }
-
-// #endregion
@@ -0,0 +1,352 @@
+use crate::ini;
+use crate::ini::Encoding;
+use crate::ini::IniFile;
+use crate::ini::IniLine;
+use crate::version::DottedVersion;
+use std::fmt;
+use std::str::FromStr;
+
+/// What Rainmeter loads once the package is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadType {
+    /// A skin, Load is Config\File.ini.
+    Skin,
+    /// A layout, Load is its name.
+    Layout,
+}
+
+impl FromStr for LoadType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("Skin") {
+            Ok(LoadType::Skin)
+        } else if s.eq_ignore_ascii_case("Layout") {
+            Ok(LoadType::Layout)
+        } else {
+            Err(())
+        }
+    }
+}
+
+/// A problem in RMSKIN.ini that doesn't stop the install.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestWarning {
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// What is wrong.
+    pub message: String,
+}
+
+impl fmt::Display for ManifestWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RMSKIN.ini line {}: {}", self.line, self.message)
+    }
+}
+
+/// Why RMSKIN.ini can't be used.
+#[derive(Debug)]
+pub enum ManifestError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The file has no `[rmskin]` section.
+    MissingSection,
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Io(e) => write!(f, "error reading RMSKIN.ini: {}", e),
+            ManifestError::MissingSection => write!(f, "RMSKIN.ini has no [rmskin] section"),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+impl From<std::io::Error> for ManifestError {
+    fn from(e: std::io::Error) -> Self {
+        ManifestError::Io(e)
+    }
+}
+
+/// The `[rmskin]` section of RMSKIN.ini.
+#[derive(Debug, Clone, Default)]
+pub struct PackageManifest {
+    /// Name, what the package is installed and uninstalled as.
+    pub name: Option<String>,
+    /// Author.
+    pub author: Option<String>,
+    /// Version.
+    pub version: Option<String>,
+    /// MinimumRainmeter.
+    pub minimum_rainmeter: Option<DottedVersion>,
+    /// MinimumWindows.
+    pub minimum_windows: Option<DottedVersion>,
+    /// LoadType, only set together with Load.
+    pub load_type: Option<LoadType>,
+    /// Load, the skin or layout to load after installing.
+    pub load: Option<String>,
+    /// VariableFiles, the files whose values are carried over.
+    pub variable_files: Vec<String>,
+    /// VariableSections, the sections of the variable files that are carried over,
+    /// `[Variables]` when empty.
+    pub variable_sections: Vec<String>,
+    /// MergeSkins, the package adds to skin roots instead of replacing them.
+    pub merge_skins: bool,
+    /// Problems found while reading the section.
+    pub warnings: Vec<ManifestWarning>,
+}
+
+impl PackageManifest {
+    /// Reads RMSKIN.ini as stored in the package.
+    pub fn from_bytes(bytes: &[u8]) -> Result<PackageManifest, ManifestError> {
+        PackageManifest::from_ini(&IniFile::from_bytes(bytes))
+    }
+
+    /// Reads RMSKIN.ini from already decoded text.
+    pub fn parse(content: &str) -> Result<PackageManifest, ManifestError> {
+        PackageManifest::from_ini(&IniFile::parse(content, Encoding::Utf8))
+    }
+
+    /// Read like every other ini file: section and key names match case-insensitively
+    /// and only the first `[rmskin]` section and the first of each key count. The lines
+    /// are walked so problems can be reported with their line number.
+    pub fn from_ini(ini: &IniFile) -> Result<PackageManifest, ManifestError> {
+        let mut manifest = PackageManifest::default();
+        let mut section_line: Option<usize> = None;
+        let mut in_rmskin = false;
+        let mut seen: Vec<String> = vec![];
+        let mut load_line = 0;
+
+        for (index, line) in ini.lines().enumerate() {
+            let line_no = index + 1;
+            let (key, value) = match line {
+                IniLine::Section(name) => {
+                    in_rmskin = name.eq_ignore_ascii_case("rmskin") && section_line.is_none();
+                    if in_rmskin {
+                        section_line = Some(line_no);
+                    } else if name.eq_ignore_ascii_case("rmskin") {
+                        manifest.warn(line_no, "duplicate [rmskin] section ignored".to_owned());
+                    }
+                    continue;
+                }
+                IniLine::Entry { key, value } => (key, ini::unquote(value)),
+                IniLine::Other(text) => {
+                    let text = text.trim();
+                    if text.starts_with('[') {
+                        in_rmskin = false;
+                        manifest.warn(line_no, format!("malformed section header \"{}\"", text));
+                    } else if in_rmskin && !text.is_empty() && !text.starts_with(';') {
+                        manifest.warn(
+                            line_no,
+                            format!("line \"{}\" is not a key=value pair", text),
+                        );
+                    }
+                    continue;
+                }
+            };
+
+            if !in_rmskin {
+                continue;
+            }
+
+            let lower = key.to_lowercase();
+            if seen.contains(&lower) {
+                manifest.warn(line_no, format!("duplicate key \"{}\" ignored", key));
+                continue;
+            }
+            seen.push(lower.clone());
+
+            if value.is_empty() {
+                continue;
+            }
+
+            match lower.as_str() {
+                "name" => manifest.name = Some(value.to_owned()),
+                "author" => manifest.author = Some(value.to_owned()),
+                "version" => manifest.version = Some(value.to_owned()),
+                "minimumrainmeter" => {
+                    manifest.minimum_rainmeter = manifest.parse_version(line_no, key, value)
+                }
+                "minimumwindows" => {
+                    manifest.minimum_windows = manifest.parse_version(line_no, key, value)
+                }
+                "loadtype" => match value.parse::<LoadType>() {
+                    Ok(load_type) => manifest.load_type = Some(load_type),
+                    Err(_) => manifest.warn(
+                        line_no,
+                        format!(
+                            "LoadType \"{}\" is not Skin or Layout, nothing will be loaded",
+                            value
+                        ),
+                    ),
+                },
+                "load" => {
+                    manifest.load = Some(value.to_owned());
+                    load_line = line_no;
+                }
+                "variablefiles" => manifest.variable_files = split_list(value),
+                "variablesections" => manifest.variable_sections = split_list(value),
+                "mergeskins" => match value.parse::<i64>() {
+                    Ok(n) => manifest.merge_skins = n != 0,
+                    Err(_) => manifest.warn(
+                        line_no,
+                        format!(
+                            "MergeSkins \"{}\" is not a number, skins will be replaced",
+                            value
+                        ),
+                    ),
+                },
+                "minimumdotnet" => manifest.warn(
+                    line_no,
+                    "MinimumDotNET is only used by legacy installers and is ignored".to_owned(),
+                ),
+                _ => manifest.warn(line_no, format!("unknown key \"{}\" ignored", key)),
+            }
+        }
+
+        let section_line = match section_line {
+            Some(line) => line,
+            None => return Err(ManifestError::MissingSection),
+        };
+
+        if manifest.name.is_none() {
+            manifest.warn(section_line, "Name is missing".to_owned());
+        }
+        if manifest.version.is_none() {
+            manifest.warn(section_line, "Version is missing".to_owned());
+        }
+
+        match (manifest.load_type, manifest.load.as_ref()) {
+            (Some(_), None) => {
+                manifest.warn(
+                    section_line,
+                    "LoadType is set but Load is missing".to_owned(),
+                );
+                manifest.load_type = None;
+            }
+            (None, Some(_)) => {
+                manifest.warn(load_line, "Load is set without a valid LoadType".to_owned());
+                manifest.load = None;
+            }
+            (Some(LoadType::Skin), Some(load)) if !load.contains('\\') => {
+                manifest.warn(
+                    load_line,
+                    format!("Load \"{}\" must be in the form Config\\File.ini", load),
+                );
+                manifest.load_type = None;
+                manifest.load = None;
+            }
+            _ => (),
+        }
+
+        Ok(manifest)
+    }
+
+    fn warn(&mut self, line: usize, message: String) {
+        self.warnings.push(ManifestWarning { line, message });
+    }
+
+    fn parse_version(&mut self, line: usize, key: &str, value: &str) -> Option<DottedVersion> {
+        match value.parse::<DottedVersion>() {
+            Ok(version) => Some(version),
+            Err(_) => {
+                self.warn(
+                    line,
+                    format!("{} \"{}\" is not a dotted version number", key, value),
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Any spacing around the separator, "a|b" is as good as "a | b".
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split('|')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warnings(manifest: &PackageManifest) -> Vec<(usize, &str)> {
+        manifest
+            .warnings
+            .iter()
+            .map(|w| (w.line, w.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_keys_case_insensitively() {
+        let manifest = PackageManifest::parse(
+            "; packaged by hand\n[RMSKIN]\nNAME=Suite\nversion = \"1.2\"\nAuthor='Someone'\nloadtype=skin\nLOAD=Suite\\Clock\\Clock.ini\nVariableFiles=Suite\\Variables.inc | Suite\\Colors.inc\nmergeskins=1\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.name.as_deref(), Some("Suite"));
+        assert_eq!(manifest.version.as_deref(), Some("1.2"));
+        // only double quotes are removed, like every other ini file
+        assert_eq!(manifest.author.as_deref(), Some("'Someone'"));
+        assert_eq!(manifest.load_type, Some(LoadType::Skin));
+        assert_eq!(manifest.load.as_deref(), Some("Suite\\Clock\\Clock.ini"));
+        assert_eq!(
+            manifest.variable_files,
+            vec!["Suite\\Variables.inc", "Suite\\Colors.inc"]
+        );
+        assert!(manifest.merge_skins);
+        assert!(manifest.warnings.is_empty());
+    }
+
+    #[test]
+    fn warns_with_line_numbers() {
+        let manifest = PackageManifest::parse(
+            "[rmskin]\nName=Suite\nname=Other\nMinimumRainmeter=four\nnot a pair\nLoad=Clock.ini\nLoadType=Skin\nColor=1\n[Other\n[rmskin]\nVersion=1.0\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.name.as_deref(), Some("Suite"));
+        assert_eq!(manifest.version, None);
+        assert_eq!(manifest.load, None);
+        assert_eq!(
+            warnings(&manifest),
+            vec![
+                (3, "duplicate key \"name\" ignored"),
+                (
+                    4,
+                    "MinimumRainmeter \"four\" is not a dotted version number"
+                ),
+                (5, "line \"not a pair\" is not a key=value pair"),
+                (8, "unknown key \"Color\" ignored"),
+                (9, "malformed section header \"[Other\""),
+                (10, "duplicate [rmskin] section ignored"),
+                (1, "Version is missing"),
+                (6, "Load \"Clock.ini\" must be in the form Config\\File.ini"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_utf16_and_requires_the_section() {
+        let bytes: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain(
+                "[rmskin]\r\nName=Suite\r\nVersion=2.0\r\n"
+                    .encode_utf16()
+                    .flat_map(|u| u.to_le_bytes()),
+            )
+            .collect();
+        let manifest = PackageManifest::from_bytes(&bytes).unwrap();
+        assert_eq!(manifest.version.as_deref(), Some("2.0"));
+
+        assert!(matches!(
+            PackageManifest::parse("[Other]\nName=Suite\n"),
+            Err(ManifestError::MissingSection)
+        ));
+    }
+}
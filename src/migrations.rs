@@ -0,0 +1,373 @@
+// rules a package declares to follow variables its author renamed, removed,
+// re-valued or moved to another file since the previous version. they are
+// read from the [rmskin.Migrations] section of RMSKIN.ini and the [Migrations]
+// section of a Migrations.ini next to it, and run in order on the installed
+// variable files before their values are carried over
+//
+// every key holds one rule, the key name only identifies it in the report
+//   Rule1=Rename|Suite\@Resources\Variables.inc|OldFont|FontFace
+//   Rule2=Remove|Suite\@Resources\Variables.inc|LegacyWidth
+//   Rule3=Transform|Suite\@Resources\Variables.inc|ShowClock|0=false,1=true
+//   Rule4=Move|Suite\@Resources\Variables.inc|Color|Suite\@Resources\Colors.inc
+//   Rule5=Move|Suite\@Resources\Variables.inc|Color|Suite\@Resources\Colors.inc|TextColor
+// a key is looked up in [Variables] unless written as Section:Key
+
+use crate::archive::PackageArchive;
+use crate::ini;
+use crate::ini::IniFile;
+use crate::manifest;
+use crate::variables;
+use crate::Error;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A key of a variable file and the section it is in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableKey {
+    /// Section name.
+    pub section: String,
+    /// Key name.
+    pub key: String,
+}
+
+impl VariableKey {
+    fn parse(value: &str) -> VariableKey {
+        match value.split_once(':') {
+            Some((section, key)) => VariableKey {
+                section: section.trim().to_owned(),
+                key: key.trim().to_owned(),
+            },
+            None => VariableKey {
+                section: variables::DEFAULT_SECTION.to_owned(),
+                key: value.to_owned(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for VariableKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.section, self.key)
+    }
+}
+
+/// What a rule does to the value of its key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationAction {
+    /// A rename is a move within the same file.
+    Move {
+        /// Relative to the Skins folder.
+        file: PathBuf,
+        /// The key the value is written to.
+        to: VariableKey,
+    },
+    /// The key is deleted.
+    Remove,
+    /// Old value to new value, values without a mapping are left alone.
+    Transform(Vec<(String, String)>),
+}
+
+/// One rule of the Migrations section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// The key the rule is written under.
+    pub rule: String,
+    /// Relative to the Skins folder.
+    pub file: PathBuf,
+    /// The key the rule applies to.
+    pub from: VariableKey,
+    /// What the rule does.
+    pub action: MigrationAction,
+}
+
+impl Migration {
+    /// Reads the rule `value` written under the key `rule`.
+    pub fn parse(rule: &str, value: &str) -> Result<Migration, String> {
+        let fields = manifest::split_list(value);
+        let field_count = |count: std::ops::RangeInclusive<usize>| {
+            if count.contains(&fields.len()) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "migration rule {} \"{}\" has the wrong number of fields",
+                    rule, value
+                ))
+            }
+        };
+        let action = match fields.first() {
+            Some(action) => action.to_ascii_lowercase(),
+            None => return Err(format!("migration rule {} is empty", rule)),
+        };
+
+        let action = match action.as_str() {
+            "rename" => {
+                field_count(4..=4)?;
+                MigrationAction::Move {
+                    file: to_path(&fields[1]),
+                    to: VariableKey::parse(&fields[3]),
+                }
+            }
+            "remove" => {
+                field_count(3..=3)?;
+                MigrationAction::Remove
+            }
+            "transform" => {
+                field_count(4..=4)?;
+                let mut mappings = vec![];
+                for mapping in fields[3].split(',') {
+                    match mapping.split_once('=') {
+                        Some((old, new)) => {
+                            mappings.push((old.trim().to_owned(), new.trim().to_owned()))
+                        }
+                        None => {
+                            return Err(format!(
+                                "migration rule {} mapping \"{}\" is not old=new",
+                                rule,
+                                mapping.trim()
+                            ))
+                        }
+                    }
+                }
+                MigrationAction::Transform(mappings)
+            }
+            "move" => {
+                field_count(4..=5)?;
+                let from = VariableKey::parse(&fields[2]);
+                MigrationAction::Move {
+                    file: to_path(&fields[3]),
+                    to: match fields.get(4) {
+                        Some(to) => VariableKey::parse(to),
+                        None => from,
+                    },
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "migration rule {} action \"{}\" is not Rename, Remove, Transform or Move",
+                    rule, fields[0]
+                ))
+            }
+        };
+
+        Ok(Migration {
+            rule: rule.to_owned(),
+            file: to_path(&fields[1]),
+            from: VariableKey::parse(&fields[2]),
+            action,
+        })
+    }
+}
+
+/// A rule that changed one of the installed variable files.
+#[derive(Debug, Clone)]
+pub struct AppliedMigration {
+    /// The key the rule is written under.
+    pub rule: String,
+    /// What the rule changed, for the report.
+    pub description: String,
+    /// The file and key a move wrote the value to.
+    pub written: Option<(PathBuf, VariableKey)>,
+}
+
+impl fmt::Display for AppliedMigration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.rule, self.description)
+    }
+}
+
+// the package's rules, RMSKIN.ini first, rules that can't be parsed are skipped with a warning
+pub(crate) fn read(
+    archive: &mut PackageArchive,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<Migration>, Error> {
+    let mut migrations = vec![];
+    for (file, section) in [
+        ("RMSKIN.ini", "rmskin.Migrations"),
+        ("Migrations.ini", "Migrations"),
+    ] {
+        let entry = match archive.find(Path::new(file)) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let ini = IniFile::from_bytes(&archive.read(&entry)?);
+        for (rule, value) in ini.entries(section) {
+            match Migration::parse(&rule, ini::unquote(&value)) {
+                Ok(migration) => migrations.push(migration),
+                Err(e) => warnings.push(format!("{}: {}, ignored", file, e)),
+            }
+        }
+    }
+    Ok(migrations)
+}
+
+// runs the rules on the files in order, a file that a move needs is created like
+// the file the key comes from. rules whose file or key isn't there do nothing
+pub(crate) fn apply(
+    migrations: &[Migration],
+    files: &mut Vec<(PathBuf, IniFile)>,
+) -> Vec<AppliedMigration> {
+    let mut applied = vec![];
+    for migration in migrations {
+        let index = match find_file(files, &migration.file) {
+            Some(index) => index,
+            None => continue,
+        };
+        let from = &migration.from;
+        let value = match files[index].1.get_raw(&from.section, &from.key) {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let mut written = None;
+        let description = match &migration.action {
+            MigrationAction::Remove => {
+                files[index].1.remove(&from.section, &from.key);
+                format!("removed {} {}", migration.file.display(), from)
+            }
+            MigrationAction::Transform(mappings) => {
+                let new = match mappings.iter().find(|(old, _)| *old == value) {
+                    Some((_, new)) => new,
+                    None => continue,
+                };
+                files[index].1.set(&from.section, &from.key, new);
+                format!(
+                    "changed {} {} from {} to {}",
+                    migration.file.display(),
+                    from,
+                    value,
+                    new
+                )
+            }
+            MigrationAction::Move { file, to } => {
+                files[index].1.remove(&from.section, &from.key);
+                let target = match find_file(files, file) {
+                    Some(target) => target,
+                    None => {
+                        let source = &files[index].1;
+                        let created = IniFile::new(source.encoding(), source.newline());
+                        files.push((file.to_owned(), created));
+                        files.len() - 1
+                    }
+                };
+                files[target].1.set(&to.section, &to.key, &value);
+                written = Some((files[target].0.to_owned(), to.clone()));
+                if target == index {
+                    format!("renamed {} {} to {}", migration.file.display(), from, to)
+                } else {
+                    format!(
+                        "moved {} {} to {} {}",
+                        migration.file.display(),
+                        from,
+                        file.display(),
+                        to
+                    )
+                }
+            }
+        };
+        applied.push(AppliedMigration {
+            rule: migration.rule.to_owned(),
+            description,
+            written,
+        });
+    }
+    applied
+}
+
+// names are compared case-insensitively like Windows does
+fn find_file(files: &[(PathBuf, IniFile)], file: &Path) -> Option<usize> {
+    let file = file.to_string_lossy().to_lowercase();
+    files
+        .iter()
+        .position(|(path, _)| path.to_string_lossy().to_lowercase() == file)
+}
+
+fn to_path(file: &str) -> PathBuf {
+    file.split(['\\', '/'])
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ini::Encoding;
+
+    #[test]
+    fn parses_rules() {
+        let rename = Migration::parse(
+            "Rule1",
+            "Rename | Suite\\Variables.inc | OldFont | FontFace",
+        )
+        .unwrap();
+        assert_eq!(rename.file, to_path("Suite\\Variables.inc"));
+        assert_eq!(rename.from.section, "Variables");
+        assert_eq!(
+            rename.action,
+            MigrationAction::Move {
+                file: to_path("Suite\\Variables.inc"),
+                to: VariableKey::parse("FontFace"),
+            }
+        );
+
+        let transform = Migration::parse(
+            "Rule2",
+            "transform|Suite\\Variables.inc|Clock:Show|0=false,1=true",
+        )
+        .unwrap();
+        assert_eq!(transform.from.section, "Clock");
+        assert_eq!(transform.from.key, "Show");
+        assert_eq!(
+            transform.action,
+            MigrationAction::Transform(vec![
+                ("0".to_owned(), "false".to_owned()),
+                ("1".to_owned(), "true".to_owned())
+            ])
+        );
+
+        assert!(Migration::parse("Rule3", "Remove|Suite\\Variables.inc").is_err());
+        assert!(Migration::parse("Rule4", "Copy|Suite\\Variables.inc|A|B").is_err());
+    }
+
+    #[test]
+    fn applies_rules_in_order() {
+        let variables = IniFile::parse(
+            "[Variables]\nOldFont=Arial\nShowClock=1\nLegacyWidth=200\nColor=255,0,0\n",
+            Encoding::Utf8,
+        );
+        let mut files = vec![(to_path("Suite\\Variables.inc"), variables)];
+        let migrations: Vec<Migration> = [
+            "Rename|Suite\\Variables.inc|OldFont|FontFace",
+            "Transform|Suite\\Variables.inc|ShowClock|0=false,1=true",
+            "Remove|Suite\\Variables.inc|LegacyWidth",
+            "Move|suite\\variables.inc|Color|Suite\\Colors.inc|Colors:Text",
+            "Remove|Suite\\Variables.inc|Missing",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| Migration::parse(&format!("Rule{}", i + 1), rule).unwrap())
+        .collect();
+
+        let applied = apply(&migrations, &mut files);
+        assert_eq!(applied.len(), 4);
+        assert_eq!(applied[3].rule, "Rule4");
+        assert_eq!(
+            applied[3].written,
+            Some((
+                to_path("Suite\\Colors.inc"),
+                VariableKey::parse("Colors:Text")
+            ))
+        );
+
+        let variables = &files[0].1;
+        assert_eq!(
+            variables.entries("Variables"),
+            vec![
+                ("ShowClock".to_owned(), "true".to_owned()),
+                ("FontFace".to_owned(), "Arial".to_owned())
+            ]
+        );
+        assert_eq!(files[1].0, to_path("Suite\\Colors.inc"));
+        assert_eq!(files[1].1.get("Colors", "Text").as_deref(), Some("255,0,0"));
+    }
+}
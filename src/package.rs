@@ -0,0 +1,268 @@
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+
+/// The footer Rainmeter appends to every .rmskin:
+/// [u64 archive size (LE)] [u8 flags] [b"RMSKIN\0"].
+pub const FOOTER_LEN: u64 = 16;
+const FOOTER_MAGIC: &[u8; 7] = b"RMSKIN\0";
+
+// zip end of central directory record
+const EOCD_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+const EOCD_LEN: u64 = 22;
+const MAX_ZIP_COMMENT: u64 = 0xFFFF;
+
+/// What the end of the package file holds.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PackageFormat {
+    /// Zip archive followed by a valid rmskin footer.
+    Rmskin {
+        /// Size of the zip archive in bytes.
+        archive_size: u64,
+        /// The footer's flags byte.
+        flags: u8,
+    },
+    /// Zip archive without any footer, usually a renamed .zip.
+    PlainZip,
+}
+
+/// Why the package is not a readable rmskin or zip.
+#[derive(Debug)]
+pub enum FooterError {
+    /// The package could not be read.
+    Io(std::io::Error),
+    /// Too small to hold a zip directory.
+    TooSmall {
+        /// Size of the package in bytes.
+        file_size: u64,
+    },
+    /// The footer doesn't end with RMSKIN\0.
+    CorruptMagic {
+        /// The bytes where the marker should be.
+        found: Vec<u8>,
+    },
+    /// The footer's archive size is not the size before the footer.
+    SizeMismatch {
+        /// Archive size written in the footer.
+        declared: u64,
+        /// Bytes before the footer.
+        actual: u64,
+    },
+    /// The footer is valid but no zip directory ends where it says.
+    ArchiveEndMissing {
+        /// Archive size written in the footer.
+        archive_size: u64,
+    },
+    /// Neither a footer nor a zip directory at the end.
+    Unrecognized {
+        /// Size of the package in bytes.
+        file_size: u64,
+    },
+}
+
+impl fmt::Display for FooterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FooterError::Io(e) => write!(f, "error reading package: {}", e),
+            FooterError::TooSmall { file_size } => write!(
+                f,
+                "package is truncated: {} bytes is too small to hold an archive and the rmskin footer",
+                file_size
+            ),
+            FooterError::CorruptMagic { found } => write!(
+                f,
+                "rmskin footer is corrupt: expected marker \"RMSKIN\\0\", found {:?}",
+                String::from_utf8_lossy(found)
+            ),
+            FooterError::SizeMismatch { declared, actual } => write!(
+                f,
+                "rmskin footer is corrupt: it declares an archive of {} bytes but the package holds {} bytes before the footer",
+                declared, actual
+            ),
+            FooterError::ArchiveEndMissing { archive_size } => write!(
+                f,
+                "rmskin footer is valid but no zip directory ends at byte {}, the archive is truncated or corrupt",
+                archive_size
+            ),
+            FooterError::Unrecognized { file_size } => write!(
+                f,
+                "package of {} bytes has neither an rmskin footer nor a zip directory at its end, the file is truncated or not a package",
+                file_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FooterError {}
+
+impl From<std::io::Error> for FooterError {
+    fn from(e: std::io::Error) -> Self {
+        FooterError::Io(e)
+    }
+}
+
+/// Reads the last bytes of the package and tells apart an rmskin, a plain zip
+/// and a damaged file without handing anything to the zip reader.
+pub fn read_package_format(path: &Path) -> Result<PackageFormat, FooterError> {
+    let mut file = fs::File::open(path)?;
+    read_format(&mut file)
+}
+
+/// Like `read_package_format` for a package already open.
+pub fn read_format<R: Read + Seek>(reader: &mut R) -> Result<PackageFormat, FooterError> {
+    let file_size = reader.seek(SeekFrom::End(0))?;
+    if file_size < EOCD_LEN {
+        return Err(FooterError::TooSmall { file_size });
+    }
+
+    if file_size >= FOOTER_LEN {
+        let mut footer = [0u8; FOOTER_LEN as usize];
+        reader.seek(SeekFrom::Start(file_size - FOOTER_LEN))?;
+        reader.read_exact(&mut footer)?;
+
+        let magic = &footer[9..];
+        if magic == FOOTER_MAGIC {
+            let declared = u64::from_le_bytes(footer[..8].try_into().unwrap());
+            let actual = file_size - FOOTER_LEN;
+            if declared != actual {
+                return Err(FooterError::SizeMismatch { declared, actual });
+            }
+            if find_archive_end(reader, actual)?.is_none() {
+                return Err(FooterError::ArchiveEndMissing {
+                    archive_size: actual,
+                });
+            }
+            return Ok(PackageFormat::Rmskin {
+                archive_size: actual,
+                flags: footer[8],
+            });
+        }
+
+        // the marker is there but damaged, don't mistake it for a plain zip
+        if magic.starts_with(b"RMSKIN") || magic.eq_ignore_ascii_case(FOOTER_MAGIC) {
+            return Err(FooterError::CorruptMagic {
+                found: magic.to_vec(),
+            });
+        }
+    }
+
+    if find_archive_end(reader, file_size)?.is_some() {
+        return Ok(PackageFormat::PlainZip);
+    }
+
+    if file_size < EOCD_LEN + FOOTER_LEN {
+        return Err(FooterError::TooSmall { file_size });
+    }
+    Err(FooterError::Unrecognized { file_size })
+}
+
+// looks for an end of central directory record whose comment ends exactly at `end`
+fn find_archive_end<R: Read + Seek>(reader: &mut R, end: u64) -> std::io::Result<Option<u64>> {
+    if end < EOCD_LEN {
+        return Ok(None);
+    }
+
+    let window = std::cmp::min(end, EOCD_LEN + MAX_ZIP_COMMENT);
+    let start = end - window;
+    let mut buffer = vec![0u8; window as usize];
+    reader.seek(SeekFrom::Start(start))?;
+    reader.read_exact(&mut buffer)?;
+
+    let mut pos = buffer.len() - EOCD_LEN as usize;
+    loop {
+        if buffer[pos..pos + 4] == EOCD_SIGNATURE {
+            let comment_len = u16::from_le_bytes([buffer[pos + 20], buffer[pos + 21]]) as usize;
+            if pos + EOCD_LEN as usize + comment_len == buffer.len() {
+                return Ok(Some(start + pos as u64));
+            }
+        }
+        if pos == 0 {
+            return Ok(None);
+        }
+        pos -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // an empty zip archive is just its end of central directory record
+    fn zip() -> Vec<u8> {
+        let mut data = EOCD_SIGNATURE.to_vec();
+        data.resize(EOCD_LEN as usize, 0);
+        data
+    }
+
+    fn rmskin(declared: u64, magic: &[u8; 7]) -> Vec<u8> {
+        let mut data = zip();
+        data.extend(declared.to_le_bytes());
+        data.push(0);
+        data.extend(magic);
+        data
+    }
+
+    fn format(data: Vec<u8>) -> Result<PackageFormat, FooterError> {
+        read_format(&mut Cursor::new(data))
+    }
+
+    #[test]
+    fn reads_valid_footers_and_plain_zips() {
+        assert_eq!(
+            format(rmskin(EOCD_LEN, FOOTER_MAGIC)).unwrap(),
+            PackageFormat::Rmskin {
+                archive_size: EOCD_LEN,
+                flags: 0
+            }
+        );
+        assert_eq!(format(zip()).unwrap(), PackageFormat::PlainZip);
+    }
+
+    #[test]
+    fn tells_corrupt_footers_apart() {
+        assert!(matches!(
+            format(rmskin(EOCD_LEN, b"RMSKIN!")),
+            Err(FooterError::CorruptMagic { .. })
+        ));
+        assert!(matches!(
+            format(rmskin(EOCD_LEN, b"rmskin\0")),
+            Err(FooterError::CorruptMagic { .. })
+        ));
+        assert!(matches!(
+            format(rmskin(1000, FOOTER_MAGIC)),
+            Err(FooterError::SizeMismatch {
+                declared: 1000,
+                actual: EOCD_LEN
+            })
+        ));
+
+        let mut data = vec![0; EOCD_LEN as usize];
+        data.extend(EOCD_LEN.to_le_bytes());
+        data.push(0);
+        data.extend(FOOTER_MAGIC);
+        assert!(matches!(
+            format(data),
+            Err(FooterError::ArchiveEndMissing { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_short_and_unknown_files() {
+        assert!(matches!(
+            format(b"RMSKIN\0".to_vec()),
+            Err(FooterError::TooSmall { file_size: 7 })
+        ));
+        assert!(matches!(
+            format(vec![0; 30]),
+            Err(FooterError::TooSmall { file_size: 30 })
+        ));
+        assert!(matches!(
+            format(vec![0; 100]),
+            Err(FooterError::Unrecognized { file_size: 100 })
+        ));
+    }
+}
@@ -0,0 +1,665 @@
+// minimal PE reader, only what the installer needs: the header fields and the VERSIONINFO resource
+// https://learn.microsoft.com/en-us/windows/win32/debug/pe-format
+// https://learn.microsoft.com/en-us/windows/win32/menurc/vs-versioninfo
+
+use crate::version::DottedVersion;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+const RT_VERSION: u32 = 16;
+const FIXED_FILE_INFO_SIGNATURE: u32 = 0xFEEF04BD;
+const FIXED_FILE_INFO_LEN: usize = 52;
+
+/// Why an executable could not be read.
+#[derive(Debug)]
+pub enum PeError {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// Not a valid PE file, with the part that is wrong.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for PeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeError::Io(e) => write!(f, "error reading executable: {}", e),
+            PeError::Malformed(what) => write!(f, "malformed executable: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for PeError {}
+
+impl From<std::io::Error> for PeError {
+    fn from(e: std::io::Error) -> Self {
+        PeError::Io(e)
+    }
+}
+
+/// The fixed part of the VERSIONINFO resource.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// FILEVERSION.
+    pub file_version: DottedVersion,
+    /// PRODUCTVERSION.
+    pub product_version: DottedVersion,
+}
+
+/// The architecture an executable is built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Machine {
+    /// 32-bit x86.
+    X86,
+    /// x64.
+    X64,
+    /// ARM64.
+    Arm64,
+    /// Any other, with the raw COFF machine value.
+    Other(u16),
+}
+
+impl Machine {
+    fn from_raw(machine: u16) -> Machine {
+        match machine {
+            0x014c => Machine::X86,
+            0x8664 => Machine::X64,
+            0xAA64 => Machine::Arm64,
+            other => Machine::Other(other),
+        }
+    }
+
+    /// Folder under Plugins\ in the package that holds plugins for this architecture.
+    pub fn plugin_folder(&self) -> Option<&'static str> {
+        match self {
+            Machine::X86 => Some("32bit"),
+            Machine::X64 => Some("64bit"),
+            Machine::Arm64 => Some("ARM64"),
+            Machine::Other(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Machine::X86 => write!(f, "x86"),
+            Machine::X64 => write!(f, "x64"),
+            Machine::Arm64 => write!(f, "ARM64"),
+            Machine::Other(raw) => write!(f, "unknown machine 0x{:04x}", raw),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    virtual_address: u32,
+    virtual_size: u32,
+    raw_size: u32,
+    raw_offset: u32,
+}
+
+/// A PE file read from memory.
+#[derive(Debug)]
+pub struct PeImage<'a> {
+    data: &'a [u8],
+    /// The COFF header's machine.
+    pub machine: Machine,
+    sections: Vec<Section>,
+    resource_rva: u32,
+    resource_size: u32,
+}
+
+impl<'a> PeImage<'a> {
+    /// Reads the headers and section table.
+    pub fn parse(data: &'a [u8]) -> Result<PeImage<'a>, PeError> {
+        if data.len() < 0x40 || &data[..2] != b"MZ" {
+            return Err(PeError::Malformed("missing MZ header"));
+        }
+
+        let pe_offset = read_u32(data, 0x3C)? as usize;
+        if data.get(pe_offset..pe_offset + 4) != Some(b"PE\0\0") {
+            return Err(PeError::Malformed("missing PE signature"));
+        }
+
+        let coff = pe_offset + 4;
+        let machine = Machine::from_raw(read_u16(data, coff)?);
+        let section_count = read_u16(data, coff + 2)? as usize;
+        let optional_size = read_u16(data, coff + 16)? as usize;
+
+        let optional = coff + 20;
+        let (directory_count_offset, directories) = match read_u16(data, optional)? {
+            0x10b => (optional + 92, optional + 96),
+            0x20b => (optional + 108, optional + 112),
+            _ => return Err(PeError::Malformed("unknown optional header magic")),
+        };
+
+        // the resource table is data directory 2
+        let directory_count = read_u32(data, directory_count_offset)?;
+        let (resource_rva, resource_size) = if directory_count > 2 {
+            (
+                read_u32(data, directories + 2 * 8)?,
+                read_u32(data, directories + 2 * 8 + 4)?,
+            )
+        } else {
+            (0, 0)
+        };
+
+        let section_table = optional + optional_size;
+        let mut sections = Vec::with_capacity(section_count);
+        for i in 0..section_count {
+            let header = section_table + i * 40;
+            sections.push(Section {
+                virtual_size: read_u32(data, header + 8)?,
+                virtual_address: read_u32(data, header + 12)?,
+                raw_size: read_u32(data, header + 16)?,
+                raw_offset: read_u32(data, header + 20)?,
+            });
+        }
+
+        Ok(PeImage {
+            data,
+            machine,
+            sections,
+            resource_rva,
+            resource_size,
+        })
+    }
+
+    /// Returns None when the image has no version resource.
+    pub fn version_info(&self) -> Result<Option<VersionInfo>, PeError> {
+        let block = match self.version_resource()? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+
+        let root = match parse_block(block) {
+            Some(root) => root,
+            None => return Err(PeError::Malformed("unreadable VS_VERSIONINFO block")),
+        };
+        if root.key != "VS_VERSION_INFO" {
+            return Ok(None);
+        }
+
+        let mut info = VersionInfo::default();
+        if root.value.len() >= FIXED_FILE_INFO_LEN {
+            let fixed = root.value;
+            if read_u32(fixed, 0)? != FIXED_FILE_INFO_SIGNATURE {
+                return Err(PeError::Malformed("bad VS_FIXEDFILEINFO signature"));
+            }
+            info.file_version = split_version(read_u32(fixed, 8)?, read_u32(fixed, 12)?);
+            info.product_version = split_version(read_u32(fixed, 16)?, read_u32(fixed, 20)?);
+        }
+
+        // some plugins leave the fixed info zeroed and only fill in the string table
+        if info.file_version.is_zero() {
+            if let Some(version) = version_string(&root, "FileVersion") {
+                info.file_version = version;
+            }
+        }
+        if info.product_version.is_zero() {
+            if let Some(version) = version_string(&root, "ProductVersion") {
+                info.product_version = version;
+            }
+        }
+
+        Ok(Some(info))
+    }
+
+    // none when no section holds the address. the section headers come from the
+    // file, a crafted one must not overflow
+    fn rva_to_offset(&self, rva: u32) -> Result<Option<usize>, PeError> {
+        let section = self.sections.iter().find(|s| {
+            let size = std::cmp::max(s.virtual_size, s.raw_size);
+            rva.checked_sub(s.virtual_address)
+                .is_some_and(|offset| offset < size)
+        });
+        match section {
+            Some(s) => match (rva - s.virtual_address).checked_add(s.raw_offset) {
+                Some(offset) => Ok(Some(offset as usize)),
+                None => Err(PeError::Malformed("section offset out of range")),
+            },
+            None => Ok(None),
+        }
+    }
+
+    // walks type -> name -> language and returns the first RT_VERSION resource
+    fn version_resource(&self) -> Result<Option<&'a [u8]>, PeError> {
+        if self.resource_rva == 0 || self.resource_size == 0 {
+            return Ok(None);
+        }
+        let root = match self.rva_to_offset(self.resource_rva)? {
+            Some(offset) => offset,
+            None => return Err(PeError::Malformed("resource table outside of any section")),
+        };
+
+        let mut entry = match self.find_entry(root, root, Some(RT_VERSION))? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        // name and language levels, take whichever comes first
+        for _ in 0..2 {
+            if entry & 0x8000_0000 == 0 {
+                return Err(PeError::Malformed("resource tree is too shallow"));
+            }
+            let directory = root + (entry & 0x7FFF_FFFF) as usize;
+            entry = match self.find_entry(root, directory, None)? {
+                Some(entry) => entry,
+                None => return Ok(None),
+            };
+        }
+        if entry & 0x8000_0000 != 0 {
+            return Err(PeError::Malformed("resource tree is too deep"));
+        }
+
+        let data_entry = root + entry as usize;
+        let data_rva = read_u32(self.data, data_entry)?;
+        let data_size = read_u32(self.data, data_entry + 4)? as usize;
+        let offset = match self.rva_to_offset(data_rva)? {
+            Some(offset) => offset,
+            None => {
+                return Err(PeError::Malformed(
+                    "version resource outside of any section",
+                ))
+            }
+        };
+        match self.data.get(offset..offset + data_size) {
+            Some(block) => Ok(Some(block)),
+            None => Err(PeError::Malformed("version resource is truncated")),
+        }
+    }
+
+    // returns the OffsetToData of the entry with the given id, or of the first entry
+    fn find_entry(
+        &self,
+        root: usize,
+        directory: usize,
+        id: Option<u32>,
+    ) -> Result<Option<u32>, PeError> {
+        if directory < root {
+            return Err(PeError::Malformed("resource directory out of bounds"));
+        }
+        let named = read_u16(self.data, directory + 12)? as usize;
+        let ids = read_u16(self.data, directory + 14)? as usize;
+        for i in 0..named + ids {
+            let entry = directory + 16 + i * 8;
+            let name = read_u32(self.data, entry)?;
+            let offset = read_u32(self.data, entry + 4)?;
+            match id {
+                Some(id) if name != id => continue,
+                _ => return Ok(Some(offset)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The version resource of the file at `path`, None if it has none.
+pub fn read_version_info(path: &Path) -> Result<Option<VersionInfo>, PeError> {
+    let data = fs::read(path)?;
+    PeImage::parse(&data)?.version_info()
+}
+
+/// The architecture of the file at `path`.
+pub fn read_machine(path: &Path) -> Result<Machine, PeError> {
+    let data = fs::read(path)?;
+    Ok(PeImage::parse(&data)?.machine)
+}
+
+// one node of the VS_VERSIONINFO tree
+struct Block<'a> {
+    key: String,
+    value: &'a [u8],
+    children: &'a [u8],
+}
+
+// wLength, wValueLength, wType, szKey, padding, Value, padding, Children
+// offsets are aligned to 32 bits relative to the start of the resource
+fn parse_block(data: &[u8]) -> Option<Block<'_>> {
+    let length = u16::from_le_bytes([*data.first()?, *data.get(1)?]) as usize;
+    let value_length = u16::from_le_bytes([*data.get(2)?, *data.get(3)?]) as usize;
+    let value_type = u16::from_le_bytes([*data.get(4)?, *data.get(5)?]);
+    let data = data.get(..length)?;
+
+    let mut pos = 6;
+    let mut key = vec![];
+    loop {
+        let unit = u16::from_le_bytes([*data.get(pos)?, *data.get(pos + 1)?]);
+        pos += 2;
+        if unit == 0 {
+            break;
+        }
+        key.push(unit);
+    }
+    pos = align4(pos);
+
+    // text values count their length in characters, binary ones in bytes
+    let value_bytes = if value_type == 1 {
+        value_length * 2
+    } else {
+        value_length
+    };
+    let value = data.get(pos..std::cmp::min(pos + value_bytes, length))?;
+    pos = std::cmp::min(align4(pos + value_bytes), length);
+
+    Some(Block {
+        key: String::from_utf16_lossy(&key),
+        value,
+        children: &data[pos..],
+    })
+}
+
+fn child_blocks(data: &[u8]) -> Vec<Block<'_>> {
+    let mut blocks = vec![];
+    let mut pos = 0;
+    while pos + 6 <= data.len() {
+        let length = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+        if length == 0 {
+            break;
+        }
+        match parse_block(&data[pos..]) {
+            Some(block) => blocks.push(block),
+            None => break,
+        }
+        pos = align4(pos + length);
+    }
+    blocks
+}
+
+// StringFileInfo -> StringTable (any language) -> String
+fn version_string(root: &Block<'_>, name: &str) -> Option<DottedVersion> {
+    for file_info in child_blocks(root.children) {
+        if file_info.key != "StringFileInfo" {
+            continue;
+        }
+        for table in child_blocks(file_info.children) {
+            for string in child_blocks(table.children) {
+                if string.key != name {
+                    continue;
+                }
+                let units = string
+                    .value
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .take_while(|u| *u != 0)
+                    .collect::<Vec<u16>>();
+                return parse_version_string(&String::from_utf16_lossy(&units));
+            }
+        }
+    }
+    None
+}
+
+// accepts "1.2.3.4", the old "1, 2, 3, 4" and trailing text like "1.2.3 (beta)"
+fn parse_version_string(s: &str) -> Option<DottedVersion> {
+    let mut parts = vec![];
+    for part in s.split(['.', ',']) {
+        let part = part.trim();
+        let digits = part
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>();
+        match digits.parse::<u32>() {
+            Ok(n) => parts.push(n),
+            Err(_) => break,
+        }
+        if digits.len() < part.len() {
+            break;
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    Some(DottedVersion(parts))
+}
+
+fn align4(pos: usize) -> usize {
+    (pos + 3) & !3
+}
+
+fn split_version(ms: u32, ls: u32) -> DottedVersion {
+    DottedVersion(vec![ms >> 16, ms & 0xFFFF, ls >> 16, ls & 0xFFFF])
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, PeError> {
+    match data.get(offset..offset + 2) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => Err(PeError::Malformed("unexpected end of file")),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, PeError> {
+    match data.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(PeError::Malformed("unexpected end of file")),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const SECTION_RVA: u32 = 0x1000;
+    const SECTION_OFFSET: usize = 0x200;
+
+    fn put_u16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16()
+            .chain([0])
+            .flat_map(|u| u.to_le_bytes())
+            .collect()
+    }
+
+    fn pad(data: &mut Vec<u8>) {
+        data.resize(align4(data.len()), 0);
+    }
+
+    // one VS_VERSIONINFO node, text values are counted in characters
+    fn block(key: &str, value: &[u8], text: bool, children: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0; 6];
+        data.extend(utf16(key));
+        pad(&mut data);
+        data.extend(value);
+        for child in children {
+            pad(&mut data);
+            data.extend(child);
+        }
+        let length = data.len() as u16;
+        let value_length = if text { value.len() / 2 } else { value.len() };
+        put_u16(&mut data, 0, length);
+        put_u16(&mut data, 2, value_length as u16);
+        put_u16(&mut data, 4, text as u16);
+        data
+    }
+
+    pub(crate) fn fixed_file_info(file: [u16; 4], product: [u16; 4]) -> Vec<u8> {
+        let mut fixed = vec![0; FIXED_FILE_INFO_LEN];
+        put_u32(&mut fixed, 0, FIXED_FILE_INFO_SIGNATURE);
+        for (offset, version) in [(8, file), (16, product)] {
+            put_u32(
+                &mut fixed,
+                offset,
+                (version[0] as u32) << 16 | version[1] as u32,
+            );
+            put_u32(
+                &mut fixed,
+                offset + 4,
+                (version[2] as u32) << 16 | version[3] as u32,
+            );
+        }
+        fixed
+    }
+
+    pub(crate) fn version_block(fixed: &[u8], strings: &[(&str, &str)]) -> Vec<u8> {
+        let strings: Vec<Vec<u8>> = strings
+            .iter()
+            .map(|(key, value)| block(key, &utf16(value), true, &[]))
+            .collect();
+        let table = block("040904b0", &[], true, &strings);
+        let string_file_info = block("StringFileInfo", &[], true, &[table]);
+        block("VS_VERSION_INFO", fixed, false, &[string_file_info])
+    }
+
+    // a dll with one section holding the resource tree type -> name -> language -> data
+    pub(crate) fn image(machine: u16, version: Option<&[u8]>) -> Vec<u8> {
+        let pe32_plus = machine != 0x014c;
+        let optional_size = if pe32_plus { 240 } else { 224 };
+        let optional = 0x40 + 4 + 20;
+        let section_table = optional + optional_size;
+
+        let mut resources = vec![0; 0x58];
+        put_u16(&mut resources, 14, 1);
+        put_u32(&mut resources, 16, RT_VERSION);
+        put_u32(&mut resources, 20, 0x8000_0018);
+        put_u16(&mut resources, 0x18 + 14, 1);
+        put_u32(&mut resources, 0x18 + 16, 1);
+        put_u32(&mut resources, 0x18 + 20, 0x8000_0030);
+        put_u16(&mut resources, 0x30 + 14, 1);
+        put_u32(&mut resources, 0x30 + 16, 0x409);
+        put_u32(&mut resources, 0x30 + 20, 0x48);
+        let block = version.unwrap_or_default();
+        put_u32(&mut resources, 0x48, SECTION_RVA + 0x58);
+        put_u32(&mut resources, 0x48 + 4, block.len() as u32);
+        resources.extend(block);
+
+        let mut data = vec![0; SECTION_OFFSET];
+        data[..2].copy_from_slice(b"MZ");
+        put_u32(&mut data, 0x3C, 0x40);
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+        put_u16(&mut data, 0x44, machine);
+        put_u16(&mut data, 0x46, 1);
+        put_u16(&mut data, 0x54, optional_size as u16);
+        let (magic, directory_count) = if pe32_plus {
+            (0x20b, optional + 108)
+        } else {
+            (0x10b, optional + 92)
+        };
+        put_u16(&mut data, optional, magic);
+        put_u32(&mut data, directory_count, 16);
+        if version.is_some() {
+            put_u32(&mut data, directory_count + 4 + 2 * 8, SECTION_RVA);
+            put_u32(
+                &mut data,
+                directory_count + 4 + 2 * 8 + 4,
+                resources.len() as u32,
+            );
+        }
+        put_u32(&mut data, section_table + 8, resources.len() as u32);
+        put_u32(&mut data, section_table + 12, SECTION_RVA);
+        put_u32(&mut data, section_table + 16, resources.len() as u32);
+        put_u32(&mut data, section_table + 20, SECTION_OFFSET as u32);
+        data.extend(resources);
+        data
+    }
+
+    fn version(s: &str) -> DottedVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn detects_machines() {
+        for (raw, machine) in [
+            (0x014c, Machine::X86),
+            (0x8664, Machine::X64),
+            (0xAA64, Machine::Arm64),
+            (0x01c4, Machine::Other(0x01c4)),
+        ] {
+            let data = image(raw, None);
+            assert_eq!(PeImage::parse(&data).unwrap().machine, machine);
+        }
+        assert_eq!(Machine::X86.plugin_folder(), Some("32bit"));
+        assert_eq!(Machine::Other(0x01c4).plugin_folder(), None);
+
+        assert!(PeImage::parse(b"not a dll").is_err());
+        let mut zip = b"PK\x03\x04".to_vec();
+        zip.resize(0x100, 0);
+        assert!(PeImage::parse(&zip).is_err());
+    }
+
+    #[test]
+    fn reads_fixed_versions() {
+        let fixed = fixed_file_info([1, 2, 3, 4], [5, 6, 7, 8]);
+        let data = image(0x8664, Some(&version_block(&fixed, &[])));
+        let info = PeImage::parse(&data)
+            .unwrap()
+            .version_info()
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.file_version, version("1.2.3.4"));
+        assert_eq!(info.product_version, version("5.6.7.8"));
+
+        let data = image(0x014c, None);
+        assert_eq!(PeImage::parse(&data).unwrap().version_info().unwrap(), None);
+    }
+
+    #[test]
+    fn falls_back_to_version_strings() {
+        let fixed = fixed_file_info([0; 4], [0; 4]);
+        let block = version_block(
+            &fixed,
+            &[
+                ("FileVersion", "2, 0, 1, 0"),
+                ("ProductVersion", "3.1 (beta)"),
+            ],
+        );
+        let data = image(0x014c, Some(&block));
+        let info = PeImage::parse(&data)
+            .unwrap()
+            .version_info()
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.file_version, version("2.0.1.0"));
+        assert_eq!(info.product_version, version("3.1"));
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let fixed = fixed_file_info([1, 2, 3, 4], [1, 2, 3, 4]);
+        let data = image(
+            0x8664,
+            Some(&version_block(&fixed, &[("FileVersion", "1.2")])),
+        );
+        assert!(PeImage::parse(&data[..0x30]).is_err());
+        assert!(PeImage::parse(&data[..0x48]).is_err());
+        assert!(PeImage::parse(&data[..0x150]).is_err());
+        // every cut must fail cleanly rather than panic
+        for len in 0..data.len() {
+            let _ = PeImage::parse(&data[..len]).and_then(|image| image.version_info());
+        }
+        let cut = PeImage::parse(&data[..SECTION_OFFSET + 0x60]).unwrap();
+        assert!(cut.version_info().is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_sections() {
+        let data = image(0x8664, None);
+        let mut image = PeImage::parse(&data).unwrap();
+        image.sections[0].raw_offset = u32::MAX - 0x10;
+        assert_eq!(
+            image.rva_to_offset(SECTION_RVA).unwrap(),
+            Some(u32::MAX as usize - 0x10)
+        );
+        assert!(matches!(
+            image.rva_to_offset(SECTION_RVA + 0x20),
+            Err(PeError::Malformed(_))
+        ));
+        assert_eq!(image.rva_to_offset(0x10).unwrap(), None);
+    }
+
+    #[test]
+    fn parses_version_strings() {
+        assert_eq!(parse_version_string("1, 2, 3, 4"), Some(version("1.2.3.4")));
+        assert_eq!(
+            parse_version_string("4.5.17 (r3700)"),
+            Some(version("4.5.17"))
+        );
+        assert_eq!(parse_version_string("beta"), None);
+    }
+}
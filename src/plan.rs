@@ -0,0 +1,988 @@
+// everything an install will do, worked out up front from the package, the
+// Rainmeter settings and what is on disk. --dry-run prints it, a real install
+// executes it, so the two can't disagree
+
+use crate::archive::ArchiveEntry;
+use crate::archive::PackageArchive;
+use crate::backups;
+use crate::backups::BackupEntry;
+use crate::backups::BackupFormat;
+use crate::backups::BackupIndex;
+use crate::conflicts;
+use crate::conflicts::FileConflict;
+use crate::conflicts::ModifiedFilePolicy;
+use crate::ini::Encoding;
+use crate::ini::IniFile;
+use crate::installer;
+use crate::installer::InstallOptions;
+use crate::manifest::LoadType;
+use crate::migrations;
+use crate::migrations::AppliedMigration;
+use crate::plugins;
+use crate::plugins::PluginDecision;
+use crate::receipts::ReceiptStore;
+use crate::storage;
+use crate::variables;
+use crate::variables::PatternMatch;
+use crate::variables::VariablePolicy;
+use crate::variables::VariableReport;
+use crate::Error;
+use crate::RainmeterSettings;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A step of an install as it is carried out, for reporting progress.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstallProgress {
+    /// Backups are being made.
+    BackingUp,
+    /// Skins, Layouts, Plugins or Addons.
+    Installing(&'static str),
+    /// The user's values are being written to the new variable files.
+    RestoringVariables,
+    /// Something went wrong that doesn't stop the install.
+    Warning(String),
+}
+
+impl fmt::Display for InstallProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstallProgress::BackingUp => write!(f, "Creating backup..."),
+            InstallProgress::Installing(component) => {
+                write!(f, "Installing {}...", component.to_lowercase())
+            }
+            InstallProgress::RestoringVariables => write!(f, "Restoring variables..."),
+            InstallProgress::Warning(warning) => write!(f, "Warning: {}", warning),
+        }
+    }
+}
+
+/// What installing a file does to the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileAction {
+    /// Nothing was there.
+    Create,
+    /// A file is replaced.
+    Overwrite,
+}
+
+/// A package file and where it is installed.
+#[derive(Debug, Clone)]
+pub struct PlannedFile {
+    /// Skins, Layouts, Plugins or Addons.
+    pub component: &'static str,
+    /// The entry in the package.
+    pub entry: ArchiveEntry,
+    /// Where it is written.
+    pub dest: PathBuf,
+    /// Whether it replaces a file.
+    pub action: FileAction,
+}
+
+/// A folder copied to a new backup generation before it is replaced.
+#[derive(Debug, Clone)]
+pub struct PlannedBackup {
+    /// Skins or Addons.
+    pub component: &'static str,
+    /// The skin or addon folder name.
+    pub name: String,
+    /// The installed folder.
+    pub source: PathBuf,
+    /// The new generation's folder or zip.
+    pub dest: PathBuf,
+    /// Every file in the installed folder.
+    pub files: Vec<PathBuf>,
+    /// The installed version being backed up.
+    pub version: Option<String>,
+    /// A copied folder or a zip.
+    pub format: BackupFormat,
+    /// Zip only, the layouts and plugins the install replaces, source and name in the zip.
+    pub extra: Vec<(PathBuf, PathBuf)>,
+    /// Zip only, the Rainmeter.ini sections of the skin's configs.
+    pub sections: Vec<String>,
+}
+
+/// A folder deleted so the new version starts clean.
+#[derive(Debug, Clone)]
+pub struct PlannedRemoval {
+    /// The folder.
+    pub path: PathBuf,
+    /// Every file in it.
+    pub files: Vec<PathBuf>,
+}
+
+/// The user's values of one section of a variable file.
+#[derive(Debug, Clone)]
+pub struct CarriedSection {
+    /// Section name.
+    pub section: String,
+    /// Keys and values as they appear in the old file.
+    pub values: Vec<(String, String)>,
+    /// What happens to each key.
+    pub report: VariableReport,
+}
+
+/// A variable file whose values are carried over.
+#[derive(Debug, Clone)]
+pub struct CarriedVariables {
+    /// Relative to the Skins folder.
+    pub file: PathBuf,
+    /// Where the new file is installed.
+    pub dest: PathBuf,
+    /// The sections carried over.
+    pub sections: Vec<CarriedSection>,
+    /// A new file that is missing is created like the old one.
+    pub encoding: Encoding,
+    /// Line ending of the old file.
+    pub newline: &'static str,
+}
+
+/// Everything an install does, worked out before anything is changed.
+#[derive(Debug, Default)]
+pub struct InstallPlan {
+    /// Backup generations made before anything is replaced.
+    pub backups: Vec<PlannedBackup>,
+    /// Older generations deleted once the new backups are made.
+    pub expired_backups: Vec<BackupEntry>,
+    /// Seconds since 1970-01-01 UTC, names the new backup generations.
+    pub created: u64,
+    /// The format new backups are written in.
+    pub backup_format: BackupFormat,
+    /// Folders deleted before the package is extracted.
+    pub removals: Vec<PlannedRemoval>,
+    /// The package's files, in zip order.
+    pub files: Vec<PlannedFile>,
+    /// What happens to each plugin.
+    pub plugins: Vec<PluginDecision>,
+    /// The files each VariableFiles pattern matches.
+    pub variable_files: Vec<PatternMatch>,
+    /// What happens to the user's values.
+    pub variable_policy: VariablePolicy,
+    /// Variable files whose values are carried over.
+    pub variables: Vec<CarriedVariables>,
+    /// The package's migration rules that changed the installed variable files.
+    pub migrations: Vec<AppliedMigration>,
+    /// The package's variable files kept for the three-way merge of the next upgrade.
+    pub pristine: Vec<PlannedFile>,
+    /// What happens to skin files the user changed.
+    pub modified_files: ModifiedFilePolicy,
+    /// Skin files the user changed since the previous install.
+    pub conflicts: Vec<FileConflict>,
+    /// Rainmeter was running and is started again afterwards.
+    pub rainmeter_running: bool,
+    /// The skin or layout loaded afterwards.
+    pub load: Option<(LoadType, String)>,
+    /// Problems found while planning that don't stop the install.
+    pub warnings: Vec<String>,
+}
+
+impl InstallPlan {
+    fn count(&self, action: FileAction) -> usize {
+        self.files
+            .iter()
+            .filter(|f| !f.entry.is_dir && f.action == action)
+            .count()
+    }
+
+    fn is_removed(&self, path: &Path) -> bool {
+        self.removals.iter().any(|r| path.starts_with(&r.path))
+    }
+
+    fn add_files(&mut self, component: &'static str, entries: Vec<(ArchiveEntry, PathBuf)>) {
+        for (entry, dest) in entries {
+            let exists = if entry.is_dir {
+                dest.is_dir()
+            } else {
+                dest.is_file()
+            };
+            let action = if exists && !self.is_removed(&dest) {
+                FileAction::Overwrite
+            } else {
+                FileAction::Create
+            };
+            self.files.push(PlannedFile {
+                component,
+                entry,
+                dest,
+                action,
+            });
+        }
+    }
+
+    fn add_backup(
+        &mut self,
+        component: &'static str,
+        name: &str,
+        source: PathBuf,
+        dest: PathBuf,
+        version: Option<&str>,
+    ) {
+        let files = list_files(&source);
+        self.backups.push(PlannedBackup {
+            component,
+            name: name.to_owned(),
+            source: source.clone(),
+            dest: backups::generation_path(&dest, self.created, version, self.backup_format),
+            files: files.clone(),
+            version: version.map(|v| v.to_owned()),
+            format: self.backup_format,
+            extra: vec![],
+            sections: vec![],
+        });
+        self.removals.push(PlannedRemoval {
+            path: source,
+            files,
+        });
+    }
+
+    /// The variable report of every carried section.
+    pub fn variable_reports(&self) -> impl Iterator<Item = &VariableReport> {
+        self.variables
+            .iter()
+            .flat_map(|v| v.sections.iter().map(|s| &s.report))
+    }
+
+    /// The variable reports of every carried file as a JSON array.
+    pub fn variable_report_json(&self) -> String {
+        let reports: Vec<&VariableReport> = self.variable_reports().collect();
+        serde_json::to_string_pretty(&reports).unwrap()
+    }
+
+    /// One line counting what the install changes.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} files created, {} overwritten, {} backed up, {} removed, {} plugins skipped, {} variable files carried over",
+            self.count(FileAction::Create),
+            self.count(FileAction::Overwrite),
+            self.backups
+                .iter()
+                .map(|b| b.files.len() + b.extra.len())
+                .sum::<usize>(),
+            self.removals.iter().map(|r| r.files.len()).sum::<usize>(),
+            self.plugins.iter().filter(|p| !p.should_copy()).count(),
+            self.variables.len(),
+        )
+    }
+}
+
+impl fmt::Display for InstallPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Install plan: {}", self.summary())?;
+
+        if !self.backups.is_empty() {
+            writeln!(f, "Backups:")?;
+            for backup in &self.backups {
+                writeln!(
+                    f,
+                    "  {} -> {}",
+                    backup.source.display(),
+                    backup.dest.display()
+                )?;
+                for file in backup
+                    .files
+                    .iter()
+                    .chain(backup.extra.iter().map(|(f, _)| f))
+                {
+                    writeln!(f, "    backup    {}", file.display())?;
+                }
+                for section in &backup.sections {
+                    writeln!(f, "    backup    Rainmeter.ini [{}]", section)?;
+                }
+            }
+        }
+
+        if !self.expired_backups.is_empty() {
+            writeln!(f, "Expired backups:")?;
+            for backup in &self.expired_backups {
+                writeln!(f, "  delete    {}", backup.path.display())?;
+            }
+        }
+
+        if !self.removals.is_empty() {
+            writeln!(f, "Removed:")?;
+            for removal in &self.removals {
+                writeln!(f, "  {}", removal.path.display())?;
+                for file in &removal.files {
+                    writeln!(f, "    remove    {}", file.display())?;
+                }
+            }
+        }
+
+        if !self.plugins.is_empty() {
+            writeln!(f, "Plugins:")?;
+            for plugin in &self.plugins {
+                writeln!(f, "  {}", plugin)?;
+            }
+        }
+
+        if !self.files.is_empty() {
+            writeln!(f, "Files:")?;
+            for file in self.files.iter().filter(|f| !f.entry.is_dir) {
+                // a file the user changed is only overwritten if the policy says so
+                let conflict = self.conflicts.iter().find(|c| c.path == file.dest);
+                let action = match (conflict.map(|c| c.policy), file.action) {
+                    (Some(ModifiedFilePolicy::Keep), _) => "keep",
+                    (Some(ModifiedFilePolicy::SideCopy), _) => "side copy",
+                    (_, FileAction::Create) => "create",
+                    (_, FileAction::Overwrite) => "overwrite",
+                };
+                writeln!(f, "  {:<9} {}", action, file.dest.display())?;
+            }
+        }
+
+        if !self.variable_files.is_empty() {
+            writeln!(f, "VariableFiles:")?;
+            for pattern in &self.variable_files {
+                writeln!(f, "  {}", pattern)?;
+            }
+        }
+
+        writeln!(f, "Modified files policy: {}", self.modified_files)?;
+        if !self.conflicts.is_empty() {
+            writeln!(f, "Modified files:")?;
+            for conflict in &self.conflicts {
+                writeln!(f, "  {}", conflict)?;
+            }
+        }
+
+        writeln!(f, "Variables policy: {}", self.variable_policy)?;
+        if !self.migrations.is_empty() {
+            writeln!(f, "Migrations applied:")?;
+            for migration in &self.migrations {
+                writeln!(f, "  {}", migration)?;
+            }
+        }
+        if !self.variables.is_empty() {
+            if self.variable_policy == VariablePolicy::PromptFile {
+                writeln!(f, "Variables carried over, asking for each file:")?;
+            } else {
+                writeln!(f, "Variables carried over:")?;
+            }
+            for report in self.variable_reports() {
+                writeln!(f, "  {}", report)?;
+            }
+        }
+
+        if !self.pristine.is_empty() {
+            writeln!(f, "Pristine copies for the next upgrade:")?;
+            for file in &self.pristine {
+                writeln!(f, "  {}", file.dest.display())?;
+            }
+        }
+
+        if self.rainmeter_running {
+            writeln!(f, "Rainmeter is running, it will be closed and restarted")?;
+        } else {
+            writeln!(f, "Rainmeter will be started")?;
+        }
+        match &self.load {
+            Some((LoadType::Skin, load)) => write!(f, "Skin to activate: {}", load),
+            Some((LoadType::Layout, load)) => write!(f, "Layout to load: {}", load),
+            None => write!(f, "Nothing will be loaded"),
+        }
+    }
+}
+
+pub(crate) fn build_plan(
+    archive: &mut PackageArchive,
+    install_options: &InstallOptions,
+    rainmeter_settings: &RainmeterSettings,
+) -> Result<InstallPlan, Error> {
+    let skins_path = Path::new(&rainmeter_settings.skins_path);
+    let settings_path = Path::new(&rainmeter_settings.settings_path);
+    let backup_dir = BackupIndex::folder(rainmeter_settings);
+    let manifest = &install_options.manifest;
+    let store = ReceiptStore::load(rainmeter_settings)?;
+    let previous = store.find_owner(
+        manifest.name.as_deref(),
+        &install_options.skins,
+        manifest.merge_skins,
+    );
+    let previous_version = previous.and_then(|r| r.version.as_deref());
+
+    let mut plan = InstallPlan {
+        created: storage::now(),
+        backup_format: install_options.backup_format,
+        rainmeter_running: install_options.was_running,
+        variable_policy: install_options.variable_policy,
+        modified_files: install_options.modified_files,
+        load: match (manifest.load_type, manifest.load.as_ref()) {
+            (Some(load_type), Some(load)) => Some((load_type, load.to_owned())),
+            _ => None,
+        },
+        ..Default::default()
+    };
+
+    // the user's values are read before anything is overwritten or moved to the backup
+    plan.variable_files =
+        variables::match_variable_files(&manifest.variable_files, skins_path, archive);
+    if install_options.variable_policy != VariablePolicy::Reset {
+        let mut varfiles: Vec<&PathBuf> = plan.variable_files.iter().flat_map(|m| &m.old).collect();
+        varfiles.sort();
+        varfiles.dedup();
+        let mut old_files = vec![];
+        let mut pristine_files = vec![];
+        for varfile in varfiles {
+            old_files.push((
+                varfile.to_owned(),
+                IniFile::read(&skins_path.join(varfile))?,
+            ));
+            if let Some(pristine) = variables::pristine_file(settings_path, varfile)? {
+                pristine_files.push((varfile.to_owned(), pristine));
+            }
+        }
+
+        // the package's migrations run on the pristine copies too so they still
+        // line up with the user's files
+        plan.migrations = migrations::apply(&install_options.migrations, &mut old_files);
+        migrations::apply(&install_options.migrations, &mut pristine_files);
+
+        for (varfile, old) in &old_files {
+            let new = variables::package_file(archive, varfile)?;
+            let pristine = pristine_files
+                .iter()
+                .find(|(file, _)| file == varfile)
+                .map(|(_, ini)| ini);
+
+            // whole selected sections, and the keys a migration moved into other sections
+            let mut selected: Vec<(String, Option<Vec<String>>)> =
+                variables::select_sections(old, &install_options.variable_sections)
+                    .into_iter()
+                    .map(|section| (section, None))
+                    .collect();
+            for (_, key) in plan
+                .migrations
+                .iter()
+                .filter_map(|m| m.written.as_ref())
+                .filter(|(file, _)| file == varfile)
+            {
+                match selected
+                    .iter_mut()
+                    .find(|(section, _)| section.eq_ignore_ascii_case(&key.section))
+                {
+                    Some((_, None)) => (),
+                    Some((_, Some(keys))) => keys.push(key.key.to_owned()),
+                    None => selected.push((key.section.to_owned(), Some(vec![key.key.to_owned()]))),
+                }
+            }
+
+            let mut sections = vec![];
+            for (section, keys) in selected {
+                let entries = |ini: Option<&IniFile>| -> Vec<(String, String)> {
+                    let mut entries = ini.map(|ini| ini.entries(&section)).unwrap_or_default();
+                    if let Some(keys) = &keys {
+                        entries.retain(|(key, _)| keys.iter().any(|k| k.eq_ignore_ascii_case(key)));
+                    }
+                    entries
+                };
+                let mut values = entries(Some(old));
+                let new_values = entries(new.as_ref());
+                let pristine_values = entries(pristine);
+                let report = VariableReport::compare(
+                    varfile,
+                    &section,
+                    &values,
+                    &new_values,
+                    &pristine_values,
+                );
+                // values the user never changed follow the new package default
+                report.retain_user_values(
+                    &mut values,
+                    install_options.variable_policy == VariablePolicy::KeepExistingKeysOnly,
+                );
+                sections.push(CarriedSection {
+                    report,
+                    section,
+                    values,
+                });
+            }
+
+            plan.variables.push(CarriedVariables {
+                file: varfile.to_owned(),
+                dest: skins_path.join(varfile),
+                sections,
+                encoding: old.encoding(),
+                newline: old.newline(),
+            });
+        }
+    }
+
+    let mut pristine: Vec<&PathBuf> = plan.variable_files.iter().flat_map(|m| &m.new).collect();
+    pristine.sort();
+    pristine.dedup();
+    for file in pristine {
+        let path = Path::new("Skins").join(file);
+        if let Some(entry) = archive.entries.iter().find(|e| e.path == path) {
+            let dest = variables::pristine_path(settings_path, file);
+            plan.pristine.push(PlannedFile {
+                component: "Pristine",
+                entry: entry.clone(),
+                action: if dest.is_file() {
+                    FileAction::Overwrite
+                } else {
+                    FileAction::Create
+                },
+                dest,
+            });
+        }
+    }
+
+    let installed_plugins = settings_path.join("Plugins");
+    let mut plugin_files = vec![];
+    for plugin in &install_options.plugins[..] {
+        let entry = installer::plugin_entry(archive, install_options, plugin).unwrap();
+        let data = archive.read(&entry)?;
+        let dest = installed_plugins.join(plugin);
+
+        let decision = plugins::check_plugin(
+            plugin,
+            &data,
+            &dest,
+            install_options.force_plugins,
+            &mut plan.warnings,
+        );
+        if decision.should_copy() {
+            plugin_files.push((entry, dest));
+        }
+        plan.plugins.push(decision);
+    }
+    plan.add_files("Plugins", plugin_files);
+
+    if !install_options.layouts.is_empty() {
+        let entries = entries_below(
+            archive,
+            Path::new("Layouts"),
+            &settings_path.join("Layouts"),
+        );
+        plan.add_files("Layouts", entries);
+    }
+
+    for addon in &install_options.addons[..] {
+        let installed = settings_path.join("Addons").join(addon);
+        if install_options.backup && installed.is_dir() {
+            plan.add_backup(
+                "Addons",
+                addon,
+                installed.clone(),
+                backup_dir.join("@Addons").join(addon),
+                previous_version,
+            );
+        }
+        let entries = entries_below(archive, &Path::new("Addons").join(addon), &installed);
+        plan.add_files("Addons", entries);
+    }
+
+    for skin in &install_options.skins[..] {
+        let installed = skins_path.join(skin);
+        if !manifest.merge_skins && install_options.backup && installed.is_dir() {
+            plan.add_backup(
+                "Skins",
+                skin,
+                installed.clone(),
+                backup_dir.join(skin),
+                previous_version,
+            );
+        }
+        let entries = entries_below(archive, &Path::new("Skins").join(skin), &installed);
+        plan.add_files("Skins", entries);
+    }
+
+    // a zip holds everything needed to put the skin back the way it was
+    if plan.backups.iter().any(|b| b.format == BackupFormat::Zip) {
+        let mut extra: Vec<(PathBuf, PathBuf)> = plan
+            .files
+            .iter()
+            .filter(|f| {
+                !f.entry.is_dir
+                    && f.action == FileAction::Overwrite
+                    && (f.component == "Layouts" || f.component == "Plugins")
+            })
+            .filter_map(|f| {
+                let name = f.dest.strip_prefix(settings_path).ok()?;
+                Some((f.dest.to_owned(), name.to_owned()))
+            })
+            .collect();
+        let rainmeter_ini = settings_path.join("Rainmeter.ini");
+        let rainmeter_ini = if rainmeter_ini.is_file() {
+            Some(IniFile::read(&rainmeter_ini)?)
+        } else {
+            None
+        };
+        for backup in &mut plan.backups {
+            if backup.format != BackupFormat::Zip || backup.component != "Skins" {
+                continue;
+            }
+            // the layouts and plugins go in the first skin's zip, once is enough to restore them
+            backup.extra = std::mem::take(&mut extra);
+            if let Some(rainmeter_ini) = &rainmeter_ini {
+                backup.sections = backups::config_sections(rainmeter_ini, &backup.name);
+            }
+        }
+    }
+
+    if !plan.backups.is_empty() {
+        let index = BackupIndex::load(rainmeter_settings)?;
+        for backup in &plan.backups {
+            plan.expired_backups.extend(index.expired(
+                backup.component,
+                &backup.name,
+                install_options.backup_retention,
+                plan.created,
+            ));
+        }
+    }
+
+    if let Some(previous) = previous {
+        let varfiles: Vec<String> = plan
+            .variable_files
+            .iter()
+            .flat_map(|m| m.old.iter().chain(&m.new))
+            .map(|file| skins_path.join(file).to_string_lossy().to_lowercase())
+            .collect();
+        plan.conflicts = conflicts::find_conflicts(
+            &plan.files,
+            previous,
+            install_options.modified_files,
+            |path| varfiles.contains(&path.to_string_lossy().to_lowercase()),
+        );
+    }
+
+    Ok(plan)
+}
+
+pub(crate) fn execute_plan(
+    archive: &mut PackageArchive,
+    plan: &InstallPlan,
+    rainmeter_settings: &RainmeterSettings,
+    progress: &mut impl FnMut(InstallProgress),
+) -> Result<(), Error> {
+    // the user's changed files are read before their folder is replaced
+    let mut user_files = vec![];
+    for conflict in &plan.conflicts {
+        if conflict.policy != ModifiedFilePolicy::Overwrite {
+            user_files.push((conflict, fs::read(&conflict.path)?));
+        }
+    }
+
+    if !plan.backups.is_empty() {
+        progress(InstallProgress::BackingUp);
+        let backup_dir = BackupIndex::folder(rainmeter_settings);
+        let mut index = BackupIndex::load(rainmeter_settings)?;
+        for backup in &plan.backups {
+            let copied = match backup.format {
+                BackupFormat::Folder => copy_dir_all(&backup.source, &backup.dest),
+                BackupFormat::Zip => write_backup_zip(backup, rainmeter_settings),
+            };
+            if let Err(error) = copied {
+                return Err(Error::Install {
+                    action: "backing up",
+                    path: backup.source.to_owned(),
+                    error,
+                });
+            }
+            index.backups.push(BackupEntry {
+                component: backup.component.to_owned(),
+                name: backup.name.to_owned(),
+                path: backup
+                    .dest
+                    .strip_prefix(&backup_dir)
+                    .unwrap_or(&backup.dest)
+                    .to_owned(),
+                created: plan.created,
+                version: backup.version.to_owned(),
+                size: backups::folder_size(&backup.dest),
+            });
+        }
+
+        // only once the new generations are safe
+        for expired in &plan.expired_backups {
+            let path = backup_dir.join(&expired.path);
+            let removed = if path.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            match removed {
+                Ok(_) => index.backups.retain(|b| b != expired),
+                Err(e) => progress(InstallProgress::Warning(format!(
+                    "could not delete expired backup {}: {}",
+                    path.display(),
+                    e
+                ))),
+            }
+        }
+        index.save(rainmeter_settings)?;
+    }
+
+    for removal in &plan.removals {
+        if let Err(error) = fs::remove_dir_all(&removal.path) {
+            return Err(Error::Install {
+                action: "removing",
+                path: removal.path.to_owned(),
+                error,
+            });
+        }
+    }
+
+    let mut component = "";
+    for file in &plan.files {
+        if file.component != component {
+            component = file.component;
+            progress(InstallProgress::Installing(component));
+        }
+        if let Err(error) = archive.extract(&file.entry, &file.dest) {
+            return Err(Error::Install {
+                action: "installing",
+                path: file.dest.to_owned(),
+                error,
+            });
+        }
+    }
+
+    for (conflict, content) in user_files {
+        let dest = conflict.side_copy.as_ref().unwrap_or(&conflict.path);
+        if let Err(error) = fs::write(dest, content) {
+            return Err(Error::Install {
+                action: "keeping modified file",
+                path: dest.to_owned(),
+                error,
+            });
+        }
+    }
+
+    if !plan.variables.is_empty() {
+        progress(InstallProgress::RestoringVariables);
+    }
+    for variables in &plan.variables {
+        let mut ini = if variables.dest.is_file() {
+            IniFile::read(&variables.dest)?
+        } else {
+            IniFile::new(variables.encoding, variables.newline)
+        };
+        for carried in &variables.sections {
+            for (key, value) in &carried.values {
+                ini.set(&carried.section, key, value);
+            }
+        }
+        if let Err(error) = ini.write(&variables.dest) {
+            return Err(Error::Install {
+                action: "restoring variables in",
+                path: variables.dest.to_owned(),
+                error,
+            });
+        }
+    }
+
+    for file in &plan.pristine {
+        if let Err(error) = archive.extract(&file.entry, &file.dest) {
+            return Err(Error::Install {
+                action: "keeping pristine copy",
+                path: file.dest.to_owned(),
+                error,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// every archive entry below `prefix` paired with where it lands below `dest`
+fn entries_below(
+    archive: &PackageArchive,
+    prefix: &Path,
+    dest: &Path,
+) -> Vec<(ArchiveEntry, PathBuf)> {
+    archive
+        .entries
+        .iter()
+        .filter_map(|entry| match entry.path.strip_prefix(prefix) {
+            Ok(relative) if !relative.as_os_str().is_empty() => {
+                Some((entry.clone(), dest.join(relative)))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// the backed up folder goes below its component, Skins\Suite\..., next to the
+// extra files and the configs' Rainmeter.ini sections
+fn write_backup_zip(
+    backup: &PlannedBackup,
+    rainmeter_settings: &RainmeterSettings,
+) -> std::io::Result<()> {
+    let folder = Path::new(backup.component).join(&backup.name);
+    let mut files: Vec<(PathBuf, PathBuf)> = backup
+        .files
+        .iter()
+        .filter_map(|file| {
+            let name = folder.join(file.strip_prefix(&backup.source).ok()?);
+            Some((file.to_owned(), name))
+        })
+        .collect();
+    files.extend(backup.extra.iter().cloned());
+
+    let rainmeter_ini = if backup.sections.is_empty() {
+        None
+    } else {
+        let installed =
+            IniFile::read(&Path::new(&rainmeter_settings.settings_path).join("Rainmeter.ini"))?;
+        let mut ini = IniFile::new(installed.encoding(), installed.newline());
+        for section in &backup.sections {
+            for (key, value) in installed.entries(section) {
+                ini.set(section, &key, &value);
+            }
+        }
+        Some(ini)
+    };
+
+    backups::write_zip(&backup.dest, &files, rainmeter_ini.as_ref())
+}
+
+fn copy_dir_all(src: &Path, dest: &Path) -> std::io::Result<()> {
+    if !src.is_dir() {
+        return Err(std::io::Error::other("Source is not a directory"));
+    }
+
+    if dest.is_file() {
+        return Err(std::io::Error::other("Destination is a file"));
+    }
+
+    if !dest.is_dir() {
+        fs::create_dir_all(dest)?;
+    }
+
+    for entry in fs::read_dir(src)? {
+        let path = entry?.path();
+        let dest_path = dest.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir_all(&path, &dest_path)?;
+        } else {
+            fs::copy(&path, &dest_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn list_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return files,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            files.extend(list_files(&path));
+        } else {
+            files.push(path);
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::backups::BackupRetention;
+    use crate::migrations::Migration;
+    use crate::pe::Machine;
+    use crate::PackageManifest;
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    pub(crate) fn package(path: &Path, files: &[(&str, &str)]) -> PackageArchive {
+        let mut zip = ZipWriter::new(fs::File::create(path).unwrap());
+        for (name, content) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        PackageArchive::open(path).unwrap()
+    }
+
+    fn options(manifest: PackageManifest, migrations: Vec<Migration>) -> InstallOptions {
+        InstallOptions {
+            was_running: false,
+            plugins: vec![],
+            skins: vec!["Suite".to_owned()],
+            layouts: vec![],
+            addons: vec![],
+            manifest,
+            force_plugins: false,
+            plugin_machine: Machine::X64,
+            backup: false,
+            backup_retention: BackupRetention::default(),
+            backup_format: BackupFormat::Folder,
+            ignore_requirements: false,
+            allow_addons: false,
+            variable_policy: VariablePolicy::Keep,
+            variable_sections: vec![variables::DEFAULT_SECTION.to_owned()],
+            modified_files: ModifiedFilePolicy::SideCopy,
+            migrations,
+        }
+    }
+
+    #[test]
+    fn carries_values_moved_out_of_the_selected_sections() {
+        let folder = std::env::temp_dir().join(format!("plan-test-{}", std::process::id()));
+        let settings = RainmeterSettings {
+            skins_path: folder.join("Skins").to_string_lossy().into_owned(),
+            application_path: String::new(),
+            settings_path: folder.join("Settings").to_string_lossy().into_owned(),
+        };
+        let resources = folder.join("Skins").join("Suite").join("@Resources");
+        fs::create_dir_all(&resources).unwrap();
+        fs::write(
+            resources.join("Variables.inc"),
+            "[Variables]\nFont=Arial\nColor=255,0,0\n",
+        )
+        .unwrap();
+
+        let mut archive = package(
+            &folder.join("Suite.rmskin"),
+            &[
+                ("Skins/Suite/Suite.ini", "[Rainmeter]\n"),
+                (
+                    "Skins/Suite/@Resources/Variables.inc",
+                    "[Variables]\nFont=Segoe UI\n",
+                ),
+                (
+                    "Skins/Suite/@Resources/Colors.inc",
+                    "[Colors]\nBackground=0,0,0\n",
+                ),
+            ],
+        );
+        let manifest = PackageManifest {
+            name: Some("Suite".to_owned()),
+            variable_files: vec!["Suite\\@Resources\\Variables.inc".to_owned()],
+            ..Default::default()
+        };
+        let migration = Migration::parse(
+            "Rule1",
+            "Move|Suite\\@Resources\\Variables.inc|Color|Suite\\@Resources\\Colors.inc|Colors:Text",
+        )
+        .unwrap();
+        let options = options(manifest, vec![migration]);
+
+        let result = build_plan(&mut archive, &options, &settings).and_then(|plan| {
+            execute_plan(&mut archive, &plan, &settings, &mut |_| ())?;
+            Ok(plan)
+        });
+        let variables = IniFile::read(&resources.join("Variables.inc"));
+        let colors = IniFile::read(&resources.join("Colors.inc"));
+        fs::remove_dir_all(&folder).unwrap();
+
+        let plan = result.unwrap();
+        assert_eq!(plan.migrations.len(), 1);
+        let variables = variables.unwrap();
+        assert_eq!(variables.get("Variables", "Font").as_deref(), Some("Arial"));
+        assert_eq!(variables.get("Variables", "Color"), None);
+        let colors = colors.unwrap();
+        assert_eq!(colors.get("Colors", "Text").as_deref(), Some("255,0,0"));
+        assert_eq!(colors.get("Colors", "Background").as_deref(), Some("0,0,0"));
+    }
+}
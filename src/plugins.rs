@@ -0,0 +1,230 @@
+use crate::pe;
+use crate::pe::Machine;
+use crate::pe::PeImage;
+use crate::pe::VersionInfo;
+use crate::version::DottedVersion;
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::Path;
+
+/// What happens to a plugin the package ships.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginAction {
+    /// Not installed yet.
+    Install,
+    /// Incoming plugin is the same or newer.
+    Replace,
+    /// Incoming plugin is older, the installed one stays.
+    Keep,
+    /// Incoming plugin is older but --force-plugins was given.
+    Downgrade,
+}
+
+/// The action for one plugin and the versions it was decided on.
+#[derive(Debug, Clone)]
+pub struct PluginDecision {
+    /// File name in the Plugins folder.
+    pub name: String,
+    /// What happens to it.
+    pub action: PluginAction,
+    /// The package's version.
+    pub incoming: Option<VersionInfo>,
+    /// The installed version.
+    pub installed: Option<VersionInfo>,
+}
+
+impl PluginDecision {
+    /// Whether the package's plugin is copied over the installed one.
+    pub fn should_copy(&self) -> bool {
+        self.action != PluginAction::Keep
+    }
+}
+
+impl fmt::Display for PluginDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let incoming = describe(self.incoming.as_ref());
+        let installed = describe(self.installed.as_ref());
+        match self.action {
+            PluginAction::Install => write!(f, "{}: installing {}", self.name, incoming),
+            PluginAction::Replace => write!(
+                f,
+                "{}: replacing {} with {}",
+                self.name, installed, incoming
+            ),
+            PluginAction::Keep => write!(
+                f,
+                "{}: keeping installed {}, package has older {}",
+                self.name, installed, incoming
+            ),
+            PluginAction::Downgrade => write!(
+                f,
+                "{}: downgrading {} to {} (forced)",
+                self.name, installed, incoming
+            ),
+        }
+    }
+}
+
+fn describe(info: Option<&VersionInfo>) -> String {
+    match info {
+        Some(info) => format!("version {}", info.file_version),
+        None => "unknown version".to_owned(),
+    }
+}
+
+/// File version decides, product version breaks ties. A plugin without a
+/// version resource counts as 0.0.0.0.
+pub fn compare_versions(
+    incoming: Option<&VersionInfo>,
+    installed: Option<&VersionInfo>,
+) -> Ordering {
+    let zero = DottedVersion::default();
+    let key = |info: Option<&VersionInfo>| match info {
+        Some(info) => (info.file_version.clone(), info.product_version.clone()),
+        None => (zero.clone(), zero.clone()),
+    };
+    key(incoming).cmp(&key(installed))
+}
+
+/// Compares the package's plugin `name` with the installed one, `exists` when
+/// there is one and `force` for --force-plugins.
+pub fn decide(
+    name: &str,
+    incoming: Option<VersionInfo>,
+    installed: Option<VersionInfo>,
+    exists: bool,
+    force: bool,
+) -> PluginDecision {
+    let action = if !exists {
+        PluginAction::Install
+    } else if compare_versions(incoming.as_ref(), installed.as_ref()) != Ordering::Less {
+        PluginAction::Replace
+    } else if force {
+        PluginAction::Downgrade
+    } else {
+        PluginAction::Keep
+    };
+
+    PluginDecision {
+        name: name.to_owned(),
+        action,
+        incoming,
+        installed,
+    }
+}
+
+/// Reads both dlls and decides whether the incoming one may overwrite the installed one,
+/// an unreadable version counts as none and is added to `warnings`.
+pub fn check_plugin(
+    name: &str,
+    incoming: &[u8],
+    installed: &Path,
+    force: bool,
+    warnings: &mut Vec<String>,
+) -> PluginDecision {
+    let exists = installed.is_file();
+    let installed_info = if exists {
+        match pe::read_version_info(installed) {
+            Ok(info) => info,
+            Err(e) => {
+                warnings.push(format!(
+                    "could not read version of installed {}: {}",
+                    name, e
+                ));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let incoming_info = match PeImage::parse(incoming).and_then(|image| image.version_info()) {
+        Ok(info) => info,
+        Err(e) => {
+            warnings.push(format!("could not read version of {}: {}", name, e));
+            None
+        }
+    };
+
+    decide(name, incoming_info, installed_info, exists, force)
+}
+
+/// A dll built for another architecture than the folder it was shipped in won't load.
+pub fn check_machine(name: &str, data: &[u8], expected: Machine) -> Result<(), String> {
+    match PeImage::parse(data).map(|image| image.machine) {
+        Ok(machine) if machine == expected => Ok(()),
+        Ok(machine) => Err(format!(
+            "{} is built for {} but was shipped as a {} plugin",
+            name, machine, expected
+        )),
+        Err(e) => Err(format!("{} is not a valid plugin: {}", name, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::tests::fixed_file_info;
+    use crate::pe::tests::image;
+    use crate::pe::tests::version_block;
+
+    fn info(file: &str, product: &str) -> Option<VersionInfo> {
+        Some(VersionInfo {
+            file_version: file.parse().unwrap(),
+            product_version: product.parse().unwrap(),
+        })
+    }
+
+    #[test]
+    fn compares_file_then_product_version() {
+        assert_eq!(
+            compare_versions(info("1.2", "1.0").as_ref(), info("1.1.9", "2.0").as_ref()),
+            Ordering::Greater
+        );
+        assert_eq!(
+            compare_versions(info("1.2", "1.0").as_ref(), info("1.2.0.0", "1.1").as_ref()),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_versions(None, info("0.0.0.0", "0").as_ref()),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn decides_what_to_do_with_plugins() {
+        let action = |incoming, installed, exists, force| {
+            decide("Audio.dll", incoming, installed, exists, force).action
+        };
+        assert_eq!(
+            action(info("1.0", "1.0"), None, false, false),
+            PluginAction::Install
+        );
+        assert_eq!(
+            action(info("2.0", "2.0"), info("1.0", "1.0"), true, false),
+            PluginAction::Replace
+        );
+        assert_eq!(
+            action(info("1.0", "1.0"), info("1.0", "1.0"), true, false),
+            PluginAction::Replace
+        );
+        assert_eq!(
+            action(info("1.0", "1.0"), info("2.0", "2.0"), true, false),
+            PluginAction::Keep
+        );
+        assert_eq!(
+            action(info("1.0", "1.0"), info("2.0", "2.0"), true, true),
+            PluginAction::Downgrade
+        );
+        assert!(!decide("Audio.dll", None, info("1.0", "1.0"), true, false).should_copy());
+    }
+
+    #[test]
+    fn checks_plugin_machines() {
+        let fixed = fixed_file_info([1, 0, 0, 0], [1, 0, 0, 0]);
+        let dll = image(0x8664, Some(&version_block(&fixed, &[])));
+        assert!(check_machine("Audio.dll", &dll, Machine::X64).is_ok());
+        assert!(check_machine("Audio.dll", &dll, Machine::X86).is_err());
+        assert!(check_machine("Audio.dll", b"MZ", Machine::X64).is_err());
+    }
+}
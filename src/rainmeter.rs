@@ -0,0 +1,118 @@
+use crate::manifest::LoadType;
+use crate::RainmeterSettings;
+use std::io;
+use std::process::Command;
+
+#[cfg(windows)]
+fn find_rainmeter_window() -> windows::Win32::Foundation::HWND {
+    unsafe {
+        windows::Win32::UI::WindowsAndMessaging::FindWindowW(
+            windows::w!("DummyRainWClass"),
+            windows::w!("Rainmeter control window"),
+        )
+    }
+}
+
+/// Whether Rainmeter's control window exists.
+#[cfg(windows)]
+pub fn is_rainmeter_running() -> bool {
+    find_rainmeter_window() != windows::Win32::Foundation::HWND(0)
+}
+
+/// Rainmeter only runs on windows, elsewhere there is nothing to close.
+#[cfg(not(windows))]
+pub fn is_rainmeter_running() -> bool {
+    false
+}
+
+#[cfg(not(windows))]
+pub(crate) fn close_rainmeter_if_running(_was_running: &mut bool) -> bool {
+    true
+}
+
+#[cfg(windows)]
+pub(crate) fn close_rainmeter_if_running(was_running: &mut bool) -> bool {
+    unsafe {
+        let hwnd = find_rainmeter_window();
+
+        if hwnd == windows::Win32::Foundation::HWND(0) {
+            return true;
+        };
+
+        *was_running = true;
+
+        let mut process_id: u32 = 0;
+
+        let _thread_id = windows::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId(
+            hwnd,
+            Some(&mut process_id as *mut u32),
+        );
+
+        match windows::Win32::System::Threading::OpenProcess(
+            windows::Win32::System::Threading::PROCESS_TERMINATE
+                | windows::Win32::System::Threading::PROCESS_SYNCHRONIZE,
+            false,
+            process_id,
+        ) {
+            Ok(process_handle) => {
+                windows::Win32::UI::WindowsAndMessaging::PostMessageW(
+                    hwnd,
+                    windows::Win32::UI::WindowsAndMessaging::WM_DESTROY,
+                    windows::Win32::Foundation::WPARAM(0),
+                    windows::Win32::Foundation::LPARAM(0),
+                );
+
+                windows::Win32::System::Threading::WaitForSingleObject(process_handle, 5000);
+                let mut lpexitcode: u32 = 0;
+                windows::Win32::System::Threading::GetExitCodeProcess(
+                    process_handle,
+                    &mut lpexitcode as *mut u32,
+                );
+                windows::Win32::Foundation::CloseHandle(process_handle);
+
+                // STILL_ACTIVE, rainmeter didn't close in time
+                lpexitcode != 259u32
+            }
+            // without a handle there is no telling whether it closed
+            Err(_) => false,
+        }
+    }
+}
+
+// starts rainmeter and loads the skin or layout the package asks for
+pub(crate) fn start_rainmeter(
+    rainmeter_settings: &RainmeterSettings,
+    load: Option<&(LoadType, String)>,
+) -> io::Result<()> {
+    let mut rainmeter_exe = rainmeter_settings.application_path.clone();
+    rainmeter_exe.push_str("Rainmeter.exe");
+
+    let mut command = Command::new("powershell");
+    command.arg("Start-Process");
+    command.arg("\"".to_owned() + &rainmeter_exe + "\"");
+
+    command.spawn()?.wait()?;
+
+    std::thread::sleep(std::time::Duration::from_millis(1000)); // wait for a for rainmeter to start
+
+    let (load_type, load) = match load {
+        Some(load) => load,
+        None => return Ok(()),
+    };
+
+    command.arg("-ArgumentList");
+    match load_type {
+        LoadType::Skin => {
+            // the manifest guarantees Config\File.ini
+            let skin = load.rfind('\\').unwrap();
+            let (skin, file) = load.split_at(skin + 1);
+            command.arg("@('[!ActivateConfig \"".to_owned() + skin + "\" \"" + file + "\"]')");
+        }
+        LoadType::Layout => {
+            command.arg("@('[!LoadLayout \"".to_owned() + load.as_str() + "\"]')");
+        }
+    }
+
+    command.spawn()?.wait()?;
+    Ok(())
+}
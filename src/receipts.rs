@@ -0,0 +1,432 @@
+// a record of every package installed and every file it wrote, kept in
+// SkinInstaller\Receipts.json below the Rainmeter settings folder so uninstall,
+// verification and upgrades know what came from where
+
+use crate::conflicts::ModifiedFilePolicy;
+use crate::installer::InstallOptions;
+use crate::plan::InstallPlan;
+use crate::storage;
+use crate::variables::VariablePolicy;
+use crate::Error;
+use crate::RainmeterSettings;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use std::fs;
+use std::io;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Bumped whenever a change to the layout would confuse an older installer.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A file the install wrote.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptFile {
+    /// Where it was written.
+    pub path: PathBuf,
+    /// Where it came from in the package, so a repair can restore it.
+    #[serde(default)]
+    pub entry: PathBuf,
+    /// Size in bytes.
+    pub size: u64,
+    /// SHA-256 as lowercase hex.
+    pub sha256: String,
+}
+
+/// The files of one skin config, layout, plugin or addon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptComponent {
+    /// Skins, Layouts, Plugins or Addons.
+    pub component: String,
+    /// The skin root, layout, plugin or addon name.
+    pub name: String,
+    /// Every file written, in zip order.
+    pub files: Vec<ReceiptFile>,
+}
+
+/// The options the package was installed with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptPolicy {
+    /// The variables argument.
+    pub variables: VariablePolicy,
+    /// Backups were made, nobackup was not given.
+    pub backup: bool,
+    /// MergeSkins of RMSKIN.ini.
+    pub merge_skins: bool,
+    /// The force-plugins argument.
+    pub force_plugins: bool,
+    /// The ignore-requirements argument.
+    pub ignore_requirements: bool,
+    /// The allow-addons argument.
+    pub allow_addons: bool,
+    /// The modified-files argument.
+    #[serde(default)]
+    pub modified_files: ModifiedFilePolicy,
+}
+
+/// One installed package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    /// Name of RMSKIN.ini, or the .rmskin file name when it has none.
+    pub name: String,
+    /// Author of RMSKIN.ini.
+    pub author: Option<String>,
+    /// Version of RMSKIN.ini.
+    pub version: Option<String>,
+    /// The .rmskin it was installed from.
+    pub archive: PathBuf,
+    /// SHA-256 of the .rmskin as lowercase hex.
+    pub archive_sha256: String,
+    /// Seconds since 1970-01-01 UTC.
+    pub installed_at: u64,
+    /// The options it was installed with.
+    pub policy: ReceiptPolicy,
+    /// What it installed, by skin config, layout, plugin and addon.
+    pub components: Vec<ReceiptComponent>,
+    /// The package's VariableFiles, relative to the Skins folder.
+    #[serde(default)]
+    pub variable_files: Vec<PathBuf>,
+    /// Skin files the user changed and kept in place of the package's.
+    #[serde(default)]
+    pub kept_files: Vec<PathBuf>,
+    /// A pinned package is never replaced.
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl Receipt {
+    // the files are hashed as they are on disk, after the variables were restored
+    pub(crate) fn from_plan(
+        skinfile: &Path,
+        install_options: &InstallOptions,
+        plan: &InstallPlan,
+    ) -> io::Result<Receipt> {
+        let manifest = &install_options.manifest;
+        let mut components: Vec<ReceiptComponent> = vec![];
+        for file in plan.files.iter().filter(|f| !f.entry.is_dir) {
+            let name = component_name(file.component, &file.entry.path);
+            // a file the user kept is still recorded as the package wrote it,
+            // so the next upgrade sees the change too
+            let kept = plan
+                .conflicts
+                .iter()
+                .find(|c| c.policy == ModifiedFilePolicy::Keep && c.path == file.dest);
+            let written = match kept {
+                Some(kept) => ReceiptFile {
+                    entry: file.entry.path.to_owned(),
+                    ..kept.recorded.clone()
+                },
+                None => ReceiptFile {
+                    path: file.dest.to_owned(),
+                    entry: file.entry.path.to_owned(),
+                    size: fs::metadata(&file.dest)?.len(),
+                    sha256: sha256_file(&file.dest)?,
+                },
+            };
+            match components
+                .iter_mut()
+                .find(|c| c.component == file.component && c.name == name)
+            {
+                Some(component) => component.files.push(written),
+                None => components.push(ReceiptComponent {
+                    component: file.component.to_owned(),
+                    name,
+                    files: vec![written],
+                }),
+            }
+        }
+
+        let mut variable_files: Vec<PathBuf> = plan
+            .variable_files
+            .iter()
+            .flat_map(|m| m.new.iter().cloned())
+            .collect();
+        variable_files.sort();
+        variable_files.dedup();
+
+        Ok(Receipt {
+            name: package_name(manifest.name.as_deref(), skinfile),
+            author: manifest.author.clone(),
+            version: manifest.version.clone(),
+            archive: skinfile.to_owned(),
+            archive_sha256: sha256_file(skinfile)?,
+            installed_at: storage::now(),
+            policy: ReceiptPolicy {
+                variables: install_options.variable_policy,
+                backup: install_options.backup,
+                merge_skins: manifest.merge_skins,
+                force_plugins: install_options.force_plugins,
+                ignore_requirements: install_options.ignore_requirements,
+                allow_addons: install_options.allow_addons,
+                modified_files: install_options.modified_files,
+            },
+            components,
+            variable_files,
+            kept_files: plan
+                .conflicts
+                .iter()
+                .filter(|c| c.policy == ModifiedFilePolicy::Keep)
+                .map(|c| c.path.to_owned())
+                .collect(),
+            pinned: false,
+        })
+    }
+
+    /// Every file of every component.
+    pub fn files(&self) -> impl Iterator<Item = &ReceiptFile> {
+        self.components.iter().flat_map(|c| &c.files)
+    }
+}
+
+/// Receipts.json, a receipt for each installed package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptStore {
+    /// The FORMAT_VERSION it was written with.
+    pub format: u32,
+    /// In install order.
+    pub receipts: Vec<Receipt>,
+}
+
+impl Default for ReceiptStore {
+    fn default() -> ReceiptStore {
+        ReceiptStore {
+            format: FORMAT_VERSION,
+            receipts: vec![],
+        }
+    }
+}
+
+impl ReceiptStore {
+    /// Where Receipts.json is kept.
+    pub fn path(settings: &RainmeterSettings) -> PathBuf {
+        Path::new(&settings.settings_path)
+            .join("SkinInstaller")
+            .join("Receipts.json")
+    }
+
+    /// An empty store if nothing was installed yet.
+    pub fn load(settings: &RainmeterSettings) -> Result<ReceiptStore, Error> {
+        let path = ReceiptStore::path(settings);
+        if !path.is_file() {
+            return Ok(ReceiptStore::default());
+        }
+        ReceiptStore::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Writes Receipts.json, replacing it only once the new one is complete.
+    pub fn save(&self, settings: &RainmeterSettings) -> Result<(), Error> {
+        storage::save_json(&ReceiptStore::path(settings), self)?;
+        Ok(())
+    }
+
+    /// Refuses a store written by a newer format.
+    pub fn from_json(json: &str) -> Result<ReceiptStore, Error> {
+        storage::parse_json(json, FORMAT_VERSION, |store: &ReceiptStore| store.format)
+            .map_err(Error::Receipts)
+    }
+
+    /// The store as Receipts.json holds it.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Package names compare case-insensitively.
+    pub fn find(&self, name: &str) -> Option<&Receipt> {
+        self.receipts
+            .iter()
+            .find(|r| r.name.to_lowercase() == name.to_lowercase())
+    }
+
+    /// Replaces the receipt of an earlier install of the same package.
+    pub fn record(&mut self, receipt: Receipt) {
+        let name = receipt.name.to_lowercase();
+        self.receipts.retain(|r| r.name.to_lowercase() != name);
+        self.receipts.push(receipt);
+    }
+
+    /// The receipt of the package by name. A package without a Name is taken to be
+    /// the one that installed any of its skin roots, unless it merges into them,
+    /// as a MergeSkins add-on shares its root with the package it adds to.
+    pub fn find_owner(
+        &self,
+        name: Option<&str>,
+        skins: &[String],
+        merge_skins: bool,
+    ) -> Option<&Receipt> {
+        if let Some(name) = name {
+            return self.find(name);
+        }
+        if merge_skins {
+            return None;
+        }
+        self.receipts.iter().find(|receipt| {
+            receipt.components.iter().any(|c| {
+                c.component == "Skins" && skins.iter().any(|s| s.eq_ignore_ascii_case(&c.name))
+            })
+        })
+    }
+
+    /// Pins or unpins the package by name.
+    pub fn set_pinned(&mut self, name: &str, pinned: bool) -> Result<(), Error> {
+        match self
+            .receipts
+            .iter_mut()
+            .find(|r| r.name.to_lowercase() == name.to_lowercase())
+        {
+            Some(receipt) => {
+                receipt.pinned = pinned;
+                Ok(())
+            }
+            None => Err(Error::NotInstalled(name.to_owned())),
+        }
+    }
+
+    /// Removes the package's receipt by name.
+    pub fn remove(&mut self, name: &str) -> Option<Receipt> {
+        let index = self
+            .receipts
+            .iter()
+            .position(|r| r.name.to_lowercase() == name.to_lowercase())?;
+        Some(self.receipts.remove(index))
+    }
+}
+
+pub(crate) fn record_install(
+    settings: &RainmeterSettings,
+    skinfile: &Path,
+    install_options: &InstallOptions,
+    plan: &InstallPlan,
+) -> Result<(), Error> {
+    let receipt = Receipt::from_plan(skinfile, install_options, plan)?;
+    let mut store = ReceiptStore::load(settings)?;
+    store.record(receipt);
+    store.save(settings)
+}
+
+/// SHA-256 of the file as lowercase hex.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+// the Name in RMSKIN.ini, the archive's file name without it
+fn package_name(name: Option<&str>, skinfile: &Path) -> String {
+    match name {
+        Some(name) => name.to_owned(),
+        None => skinfile
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+// the skin config, layout or addon folder a file is in, plugins are single files
+fn component_name(component: &str, entry: &Path) -> String {
+    let parts: Vec<String> = entry
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    if component == "Plugins" {
+        return parts.last().cloned().unwrap_or_default();
+    }
+    parts.get(1).cloned().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(name: &str, version: &str) -> Receipt {
+        Receipt {
+            name: name.to_owned(),
+            author: None,
+            version: Some(version.to_owned()),
+            archive: PathBuf::from("Suite.rmskin"),
+            archive_sha256: "00".to_owned(),
+            installed_at: 0,
+            policy: ReceiptPolicy {
+                variables: VariablePolicy::Keep,
+                backup: true,
+                merge_skins: false,
+                force_plugins: false,
+                ignore_requirements: false,
+                allow_addons: false,
+                modified_files: ModifiedFilePolicy::SideCopy,
+            },
+            components: vec![ReceiptComponent {
+                component: "Skins".to_owned(),
+                name: name.to_owned(),
+                files: vec![ReceiptFile {
+                    path: PathBuf::from("Skins").join(name).join("Clock.ini"),
+                    entry: PathBuf::from("Skins").join(name).join("Clock.ini"),
+                    size: 12,
+                    sha256: "ab".to_owned(),
+                }],
+            }],
+            variable_files: vec![],
+            kept_files: vec![],
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn round_trips_and_replaces_receipts() {
+        let mut store = ReceiptStore::default();
+        store.record(receipt("Suite", "1.0"));
+        store.record(receipt("Other", "2.0"));
+        store.record(receipt("suite", "1.1"));
+
+        let store = ReceiptStore::from_json(&store.to_json()).unwrap();
+        assert_eq!(store.receipts.len(), 2);
+        assert_eq!(store.find("SUITE").unwrap().version.as_deref(), Some("1.1"));
+        assert_eq!(store.find("Suite").unwrap().files().count(), 1);
+    }
+
+    #[test]
+    fn finds_owners_by_name_before_skin_root() {
+        let mut store = ReceiptStore::default();
+        store.record(receipt("Suite", "2.0"));
+        let skins = vec!["Suite".to_owned()];
+
+        assert_eq!(store.find_owner(Some("Pack"), &skins, true), None);
+        assert_eq!(store.find_owner(Some("Pack"), &skins, false), None);
+        assert_eq!(store.find_owner(None, &skins, true), None);
+        assert_eq!(
+            store
+                .find_owner(None, &skins, false)
+                .map(|r| r.name.as_str()),
+            Some("Suite")
+        );
+        assert_eq!(
+            store
+                .find_owner(Some("suite"), &skins, true)
+                .map(|r| r.name.as_str()),
+            Some("Suite")
+        );
+    }
+
+    #[test]
+    fn refuses_newer_formats() {
+        let json = format!("{{\"format\": {}, \"receipts\": []}}", FORMAT_VERSION + 1);
+        assert!(ReceiptStore::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn names_components() {
+        let path: PathBuf = ["Skins", "Suite", "Clock", "Clock.ini"].iter().collect();
+        assert_eq!(component_name("Skins", &path), "Suite");
+        let path: PathBuf = ["Plugins", "64bit", "Audio.dll"].iter().collect();
+        assert_eq!(component_name("Plugins", &path), "Audio.dll");
+    }
+}
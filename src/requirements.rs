@@ -0,0 +1,149 @@
+use crate::manifest::PackageManifest;
+use crate::pe;
+use crate::version::DottedVersion;
+use std::fmt;
+use std::path::Path;
+
+/// A MinimumRainmeter or MinimumWindows the system doesn't meet.
+#[derive(Debug)]
+pub struct UnmetRequirement {
+    /// Rainmeter or Windows.
+    pub component: &'static str,
+    /// The version the package asks for.
+    pub required: DottedVersion,
+    /// The installed version, None when it couldn't be read.
+    pub installed: Option<DottedVersion>,
+}
+
+impl fmt::Display for UnmetRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.installed {
+            Some(installed) => write!(
+                f,
+                "this package requires {} {} or newer, installed version is {}",
+                self.component, self.required, installed
+            ),
+            None => write!(
+                f,
+                "this package requires {} {} or newer, the installed version could not be determined",
+                self.component, self.required
+            ),
+        }
+    }
+}
+
+/// Compares the MinimumRainmeter and MinimumWindows keys against the given versions.
+pub fn unmet_requirements(
+    manifest: &PackageManifest,
+    rainmeter: Option<&DottedVersion>,
+    windows: Option<&DottedVersion>,
+) -> Vec<UnmetRequirement> {
+    let checks = [
+        ("Rainmeter", manifest.minimum_rainmeter.as_ref(), rainmeter),
+        ("Windows", manifest.minimum_windows.as_ref(), windows),
+    ];
+
+    let mut unmet = vec![];
+    for (component, required, installed) in checks {
+        let required = match required {
+            Some(required) => required,
+            None => continue,
+        };
+        if installed.is_none_or(|installed| installed < required) {
+            unmet.push(UnmetRequirement {
+                component,
+                required: required.clone(),
+                installed: installed.cloned(),
+            });
+        }
+    }
+    unmet
+}
+
+/// The version Rainmeter reports is the product version of Rainmeter.exe.
+pub fn rainmeter_version(
+    application_path: &Path,
+    warnings: &mut Vec<String>,
+) -> Option<DottedVersion> {
+    let rainmeter_exe = application_path.join("Rainmeter.exe");
+    match pe::read_version_info(&rainmeter_exe) {
+        Ok(Some(info)) => Some(info.product_version),
+        Ok(None) => {
+            warnings.push("Rainmeter.exe has no version resource".to_owned());
+            None
+        }
+        Err(e) => {
+            warnings.push(format!("could not read Rainmeter.exe version: {}", e));
+            None
+        }
+    }
+}
+
+/// GetVersionEx lies to unmanifested processes, kernel32.dll always carries the real version.
+pub fn windows_version(warnings: &mut Vec<String>) -> Option<DottedVersion> {
+    let system_root = std::env::var("SystemRoot").unwrap_or("C:\\Windows".to_owned());
+    let kernel32 = Path::new(&system_root)
+        .join("System32")
+        .join("kernel32.dll");
+    match pe::read_version_info(&kernel32) {
+        Ok(Some(info)) => os_version(&info.product_version),
+        Ok(None) => None,
+        Err(e) => {
+            warnings.push(format!("could not read Windows version: {}", e));
+            None
+        }
+    }
+}
+
+// major.minor.build, MinimumWindows=10.0.22000 is how Windows 11 is asked for.
+// none if the version resource held no version at all
+fn os_version(product_version: &DottedVersion) -> Option<DottedVersion> {
+    if product_version.is_zero() {
+        return None;
+    }
+    Some(DottedVersion(
+        product_version.0.iter().take(3).copied().collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> DottedVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn keeps_the_windows_build() {
+        assert_eq!(
+            os_version(&version("10.0.22621.2506")),
+            Some(version("10.0.22621"))
+        );
+        assert_eq!(os_version(&version("6.1")), Some(version("6.1")));
+        assert_eq!(os_version(&DottedVersion(vec![])), None);
+
+        let manifest = PackageManifest {
+            minimum_windows: Some(version("10.0.22000")),
+            ..Default::default()
+        };
+        let windows_11 = os_version(&version("10.0.22621.2506"));
+        assert!(unmet_requirements(&manifest, None, windows_11.as_ref()).is_empty());
+        let windows_10 = os_version(&version("10.0.19045.3570"));
+        let unmet = unmet_requirements(&manifest, None, windows_10.as_ref());
+        assert_eq!(unmet.len(), 1);
+        assert_eq!(unmet[0].component, "Windows");
+    }
+
+    #[test]
+    fn reports_unknown_versions_as_unmet() {
+        let manifest = PackageManifest {
+            minimum_rainmeter: Some(version("4.5")),
+            ..Default::default()
+        };
+        assert!(unmet_requirements(&manifest, Some(&version("4.5.17")), None).is_empty());
+        let unmet = unmet_requirements(&manifest, None, None);
+        assert_eq!(unmet.len(), 1);
+        assert_eq!(unmet[0].installed, None);
+    }
+}
@@ -0,0 +1,481 @@
+// checks every entry name in the package before anything is written to disk
+// enclosed_name only guards against escaping the target folder, Windows has
+// plenty of other ways to turn a zip entry into something unexpected
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::io::Seek;
+use unicode_normalization::UnicodeNormalization;
+use zip::ZipArchive;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+const RESERVED_NAMES: [&str; 4] = ["CON", "PRN", "AUX", "NUL"];
+const INVALID_CHARACTERS: [char; 6] = ['<', '>', '"', '|', '?', '*'];
+
+/// Why an entry name is unsafe to extract.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryProblem {
+    /// Absolute, drive relative or climbing out with ..
+    UnsafePath,
+    /// The same name as an earlier entry.
+    Duplicate,
+    /// Differs from an earlier entry only by letter case.
+    CaseCollision {
+        /// The earlier entry.
+        other: String,
+    },
+    /// Differs from an earlier entry only by unicode normalization.
+    NormalizationCollision {
+        /// The earlier entry.
+        other: String,
+    },
+    /// A part of the path is CON, PRN, AUX, NUL, COM1-9 or LPT1-9.
+    ReservedName {
+        /// The part of the path.
+        component: String,
+    },
+    /// A part of the path ends with a dot or space.
+    TrailingDotOrSpace {
+        /// The part of the path.
+        component: String,
+    },
+    /// A part of the path names an alternate data stream with a colon.
+    AlternateDataStream {
+        /// The part of the path.
+        component: String,
+    },
+    /// A character Windows doesn't allow in file names.
+    InvalidCharacter {
+        /// The character.
+        character: char,
+    },
+    /// A symbolic link.
+    Symlink,
+}
+
+/// An entry of the package refused and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedEntry {
+    /// The name as stored in the zip.
+    pub name: String,
+    /// What is wrong with it.
+    pub problem: EntryProblem,
+}
+
+impl fmt::Display for RejectedEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\": ", self.name)?;
+        match &self.problem {
+            EntryProblem::UnsafePath => write!(f, "path points outside of the package"),
+            EntryProblem::Duplicate => write!(f, "entry appears more than once"),
+            EntryProblem::CaseCollision { other } => {
+                write!(
+                    f,
+                    "collides with \"{}\" on a case-insensitive file system",
+                    other
+                )
+            }
+            EntryProblem::NormalizationCollision { other } => {
+                write!(
+                    f,
+                    "is the same name as \"{}\" in a different unicode form",
+                    other
+                )
+            }
+            EntryProblem::ReservedName { component } => {
+                write!(f, "\"{}\" is a reserved Windows device name", component)
+            }
+            EntryProblem::TrailingDotOrSpace { component } => write!(
+                f,
+                "\"{}\" ends with a dot or space, which Windows silently strips",
+                component
+            ),
+            EntryProblem::AlternateDataStream { component } => {
+                write!(f, "\"{}\" names an alternate data stream", component)
+            }
+            EntryProblem::InvalidCharacter { character } => {
+                write!(
+                    f,
+                    "contains {:?}, which is not allowed in Windows file names",
+                    character
+                )
+            }
+            EntryProblem::Symlink => write!(f, "entry is a symbolic link"),
+        }
+    }
+}
+
+/// The package has entries that are unsafe to extract.
+#[derive(Debug)]
+pub struct SanitizeError {
+    /// Every unsafe entry.
+    pub rejected: Vec<RejectedEntry>,
+}
+
+impl fmt::Display for SanitizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "package contains {} unsafe entries:",
+            self.rejected.len()
+        )?;
+        for entry in &self.rejected {
+            write!(f, "\n  {}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SanitizeError {}
+
+/// An entry name as stored in the zip, before anything is checked.
+#[derive(Debug, Clone)]
+pub struct RawEntry {
+    /// The name as stored in the zip.
+    pub name: String,
+    /// Whether the entry is a folder.
+    pub is_dir: bool,
+    /// The mode bits of entries made on Unix.
+    pub unix_mode: Option<u32>,
+}
+
+/// Checks every entry name of the zip without reading any data.
+pub fn check_archive<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<Vec<RejectedEntry>, zip::result::ZipError> {
+    let mut entries = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        let file = archive.by_index_raw(index)?;
+        entries.push(RawEntry {
+            name: file.name().to_owned(),
+            is_dir: file.is_dir(),
+            unix_mode: file.unix_mode(),
+        });
+    }
+    Ok(check_entries(&entries))
+}
+
+/// The entries unsafe to extract on Windows, in zip order.
+pub fn check_entries(entries: &[RawEntry]) -> Vec<RejectedEntry> {
+    let mut rejected = vec![];
+    // exact name, normalized name and folded name of every file seen so far
+    let mut exact: Vec<&str> = vec![];
+    let mut normalized: HashMap<String, &str> = HashMap::new();
+    let mut folded: HashMap<String, &str> = HashMap::new();
+
+    for entry in entries {
+        let mut reject = |problem: EntryProblem| {
+            rejected.push(RejectedEntry {
+                name: entry.name.clone(),
+                problem,
+            })
+        };
+
+        if entry.unix_mode.is_some_and(|mode| mode & S_IFMT == S_IFLNK) {
+            reject(EntryProblem::Symlink);
+            continue;
+        }
+
+        if let Some(problem) = check_name(&entry.name) {
+            reject(problem);
+            continue;
+        }
+
+        // folders may repeat harmlessly, they all end up as the same directory
+        if entry.is_dir {
+            continue;
+        }
+
+        let name = entry.name.replace('\\', "/");
+        let nfc = name.nfc().collect::<String>();
+        let fold = nfc.to_lowercase();
+
+        if exact.contains(&entry.name.as_str()) {
+            reject(EntryProblem::Duplicate);
+        } else if let Some(other) = normalized.get(&nfc) {
+            reject(EntryProblem::NormalizationCollision {
+                other: other.to_string(),
+            });
+        } else if let Some(other) = folded.get(&fold) {
+            reject(EntryProblem::CaseCollision {
+                other: other.to_string(),
+            });
+        } else {
+            exact.push(&entry.name);
+            normalized.insert(nfc, &entry.name);
+            folded.insert(fold, &entry.name);
+        }
+    }
+
+    rejected
+}
+
+fn check_name(name: &str) -> Option<EntryProblem> {
+    if name.starts_with('/') || name.starts_with('\\') || name.contains('\0') {
+        return Some(EntryProblem::UnsafePath);
+    }
+
+    for (index, component) in name.split(['/', '\\']).enumerate() {
+        if component.is_empty() || component == "." {
+            continue;
+        }
+        if component == ".." {
+            return Some(EntryProblem::UnsafePath);
+        }
+
+        if let Some(position) = component.find(':') {
+            // C:foo is drive relative, anything else is a stream
+            if index == 0 && position == 1 && component.as_bytes()[0].is_ascii_alphabetic() {
+                return Some(EntryProblem::UnsafePath);
+            }
+            return Some(EntryProblem::AlternateDataStream {
+                component: component.to_owned(),
+            });
+        }
+
+        if let Some(character) = component
+            .chars()
+            .find(|c| INVALID_CHARACTERS.contains(c) || (*c as u32) < 0x20)
+        {
+            return Some(EntryProblem::InvalidCharacter { character });
+        }
+
+        if component.ends_with('.') || component.ends_with(' ') {
+            return Some(EntryProblem::TrailingDotOrSpace {
+                component: component.to_owned(),
+            });
+        }
+
+        if is_reserved(component) {
+            return Some(EntryProblem::ReservedName {
+                component: component.to_owned(),
+            });
+        }
+    }
+
+    None
+}
+
+// CON, CON.txt and "CON .ini" all open the console device
+fn is_reserved(component: &str) -> bool {
+    let stem = component
+        .split('.')
+        .next()
+        .unwrap_or("")
+        .trim_end()
+        .to_ascii_uppercase();
+
+    if RESERVED_NAMES.contains(&stem.as_str()) {
+        return true;
+    }
+
+    let mut chars = stem.chars();
+    let prefix = chars.by_ref().take(3).collect::<String>();
+    let rest = chars.collect::<String>();
+    (prefix == "COM" || prefix == "LPT")
+        && matches!(
+            rest.as_str(),
+            "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "\u{b9}" | "\u{b2}" | "\u{b3}"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    // builds an in-memory archive with the given entry names, names ending in / are folders
+    fn archive(entries: &[&str]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in entries {
+            if name.ends_with('/') {
+                writer.add_directory(*name, FileOptions::default()).unwrap();
+            } else {
+                writer.start_file(*name, FileOptions::default()).unwrap();
+                writer.write_all(b"[Variables]\n").unwrap();
+            }
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn problems(entries: &[&str]) -> Vec<EntryProblem> {
+        check_archive(&mut archive(entries))
+            .unwrap()
+            .into_iter()
+            .map(|r| r.problem)
+            .collect()
+    }
+
+    #[test]
+    fn accepts_regular_package() {
+        let entries = [
+            "RMSKIN.ini",
+            "Skins/",
+            "Skins/Suite/",
+            "Skins/Suite/@Resources/Variables.inc",
+            "Skins/Suite/Clock/Clock.ini",
+            "Layouts/Suite/Rainmeter.ini",
+            "Plugins/64bit/Foo.dll",
+            "Skins/Suite/Console/Console Log.ini",
+            "Skins/Suite/.hidden",
+        ];
+        assert_eq!(problems(&entries), vec![]);
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        let entries = [
+            "../evil.ini",
+            "Skins/../../evil.ini",
+            "/etc/evil.ini",
+            "\\Windows\\evil.ini",
+            "C:evil.ini",
+        ];
+        assert_eq!(problems(&entries), vec![EntryProblem::UnsafePath; 5]);
+    }
+
+    #[test]
+    fn rejects_duplicates() {
+        let entries = ["Skins/Suite/Main.ini", "Skins/Suite/Main.ini"];
+        assert_eq!(problems(&entries), vec![EntryProblem::Duplicate]);
+    }
+
+    #[test]
+    fn allows_repeated_folders() {
+        let entries = ["Skins/Suite/", "Skins/Suite/", "Skins/Suite/Main.ini"];
+        assert_eq!(problems(&entries), vec![]);
+    }
+
+    #[test]
+    fn rejects_case_collisions() {
+        let entries = ["Skins/Suite/Main.ini", "Skins/suite/MAIN.ini"];
+        assert_eq!(
+            problems(&entries),
+            vec![EntryProblem::CaseCollision {
+                other: "Skins/Suite/Main.ini".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_normalization_collisions() {
+        // precomposed and decomposed e with acute accent
+        let entries = ["Skins/Caf\u{e9}/Main.ini", "Skins/Cafe\u{301}/Main.ini"];
+        assert_eq!(
+            problems(&entries),
+            vec![EntryProblem::NormalizationCollision {
+                other: "Skins/Caf\u{e9}/Main.ini".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_names() {
+        let entries = [
+            "Skins/Suite/CON",
+            "Skins/Suite/nul.ini",
+            "Skins/COM1/Main.ini",
+            "Skins/Suite/lpt9.txt",
+            "Skins/Suite/AUX .inc",
+            "Skins/Suite/COM\u{b9}.ini",
+        ];
+        let found = problems(&entries);
+        assert_eq!(found.len(), entries.len());
+        assert!(found
+            .iter()
+            .all(|p| matches!(p, EntryProblem::ReservedName { .. })));
+    }
+
+    #[test]
+    fn accepts_names_that_only_start_like_reserved_ones() {
+        let entries = [
+            "Skins/Console/Main.ini",
+            "Skins/Suite/COM10.ini",
+            "Skins/Suite/Nullable.inc",
+        ];
+        assert_eq!(problems(&entries), vec![]);
+    }
+
+    #[test]
+    fn rejects_trailing_dots_and_spaces() {
+        let entries = [
+            "Skins/Suite./Main.ini",
+            "Skins/Suite/Main.ini ",
+            "Skins/Suite/Main.ini.",
+        ];
+        let found = problems(&entries);
+        assert_eq!(found.len(), 3);
+        assert!(found
+            .iter()
+            .all(|p| matches!(p, EntryProblem::TrailingDotOrSpace { .. })));
+    }
+
+    #[test]
+    fn rejects_alternate_data_streams() {
+        let entries = ["Skins/Suite/Main.ini:stream", "Skins/Suite/Main.ini::$DATA"];
+        let found = problems(&entries);
+        assert_eq!(found.len(), 2);
+        assert!(found
+            .iter()
+            .all(|p| matches!(p, EntryProblem::AlternateDataStream { .. })));
+    }
+
+    #[test]
+    fn rejects_invalid_characters() {
+        let entries = [
+            "Skins/Suite/Ma?in.ini",
+            "Skins/Suite/a|b.ini",
+            "Skins/Suite/tab\t.ini",
+        ];
+        let found = problems(&entries);
+        assert_eq!(
+            found,
+            vec![
+                EntryProblem::InvalidCharacter { character: '?' },
+                EntryProblem::InvalidCharacter { character: '|' },
+                EntryProblem::InvalidCharacter { character: '\t' },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_symlinks() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_symlink("Skins/Suite/link", "C:\\Windows", FileOptions::default())
+            .unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        let found = check_archive(&mut archive).unwrap();
+        assert_eq!(
+            found,
+            vec![RejectedEntry {
+                name: "Skins/Suite/link".to_owned(),
+                problem: EntryProblem::Symlink
+            }]
+        );
+    }
+
+    #[test]
+    fn reports_every_offending_entry() {
+        let entries = [
+            "Skins/Suite/Main.ini",
+            "Skins/CON/x.ini",
+            "Skins/Suite/MAIN.INI",
+            "../x",
+        ];
+        let error = SanitizeError {
+            rejected: check_archive(&mut archive(&entries)).unwrap(),
+        };
+        let message = error.to_string();
+        assert!(message.contains("3 unsafe entries"));
+        assert!(message.contains("\"Skins/CON/x.ini\""));
+        assert!(message.contains("\"Skins/Suite/MAIN.INI\""));
+        assert!(message.contains("\"../x\""));
+    }
+}
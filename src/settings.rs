@@ -0,0 +1,79 @@
+use crate::ini::IniFile;
+use crate::pe;
+use crate::pe::Machine;
+use crate::Error;
+use std::fs;
+use std::path::Path;
+
+/// Where Rainmeter lives and keeps its skins and settings.
+#[derive(Debug, Clone)]
+pub struct RainmeterSettings {
+    /// Skins folder, `%USERPROFILE%\Documents\Rainmeter\Skins` unless Rainmeter.ini sets SkinPath.
+    pub skins_path: String,
+    /// Program folder, `%PROGRAMFILES%\Rainmeter`.
+    pub application_path: String,
+    /// Settings folder holding Rainmeter.ini, Plugins, Layouts and Addons, `%APPDATA%\Rainmeter`.
+    pub settings_path: String,
+}
+
+impl RainmeterSettings {
+    /// Reads the settings of the standard Rainmeter installation.
+    pub fn detect() -> Result<RainmeterSettings, Error> {
+        // only support standard rainmeter installation
+        RainmeterSettings::from_paths(
+            &(std::env::var("PROGRAMFILES").unwrap_or("".to_owned()) + "\\Rainmeter\\"),
+            &(std::env::var("APPDATA").unwrap_or("".to_owned()) + "\\Rainmeter\\"),
+        )
+    }
+
+    /// Reads the settings of a Rainmeter installed in `application_path` whose
+    /// Rainmeter.ini is in `settings_path`.
+    pub fn from_paths(
+        application_path: &str,
+        settings_path: &str,
+    ) -> Result<RainmeterSettings, Error> {
+        if !Path::new(application_path).exists()
+            || !Path::new(settings_path).join("Rainmeter.ini").exists()
+        {
+            return Err(Error::RainmeterNotFound);
+        }
+
+        let settings_filepath = Path::new(settings_path).join("Rainmeter.ini");
+        let settings = match IniFile::read(&settings_filepath) {
+            Ok(ini) => ini,
+            Err(e) => return Err(Error::Settings(e.to_string())),
+        };
+
+        let skins_path = match settings.get("Rainmeter", "SkinPath") {
+            Some(skin_path) if !skin_path.is_empty() => skin_path,
+            _ => {
+                std::env::var("USERPROFILE").unwrap_or("".to_owned())
+                    + "\\Documents\\Rainmeter\\Skins\\"
+            }
+        };
+
+        if !Path::new(&skins_path).is_dir() && fs::create_dir_all(&skins_path).is_err() {
+            return Err(Error::Settings(
+                "Rainmeter skins folder not found and could not be created".to_owned(),
+            ));
+        }
+
+        Ok(RainmeterSettings {
+            skins_path,
+            application_path: application_path.to_owned(),
+            settings_path: settings_path.to_owned(),
+        })
+    }
+
+    /// The architecture Rainmeter.exe is built for, which decides the plugin folder used.
+    pub fn machine(&self) -> Result<Machine, Error> {
+        let rainmeter_exe = Path::new(&self.application_path).join("Rainmeter.exe");
+
+        let machine = pe::read_machine(&rainmeter_exe)?;
+        if machine.plugin_folder().is_none() {
+            return Err(Error::UnsupportedArchitecture(machine));
+        }
+
+        Ok(machine)
+    }
+}
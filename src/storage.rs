@@ -0,0 +1,43 @@
+// the JSON files the installer keeps its state in, Receipts.json and Backups.json.
+// both carry a format number so an older installer won't misread a newer file
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+// written next to the file and renamed over it so a failed write can't lose it
+pub(crate) fn save_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, serde_json::to_string_pretty(value).unwrap())?;
+    fs::rename(&temp, path)
+}
+
+// `format` reads the format number of the parsed file, newer than `supported` is refused
+pub(crate) fn parse_json<T: DeserializeOwned>(
+    json: &str,
+    supported: u32,
+    format: impl Fn(&T) -> u32,
+) -> Result<T, String> {
+    let value: T = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    if format(&value) > supported {
+        return Err(format!(
+            "format {} was written by a newer installer",
+            format(&value)
+        ));
+    }
+    Ok(value)
+}
+
+// seconds since 1970-01-01 UTC
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,527 @@
+// removes a package recorded in the install receipts: its skin configs, layouts,
+// addons and the plugins nothing else needs
+
+use crate::backups;
+use crate::backups::BackupEntry;
+use crate::backups::BackupFormat;
+use crate::backups::BackupIndex;
+use crate::ini::IniFile;
+use crate::plan;
+use crate::rainmeter;
+use crate::receipts::Receipt;
+use crate::receipts::ReceiptStore;
+use crate::storage;
+use crate::variables;
+use crate::Error;
+use crate::RainmeterSettings;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A plugin of the package that stays installed.
+#[derive(Debug, Clone)]
+pub struct KeptPlugin {
+    /// File name in the Plugins folder.
+    pub name: String,
+    /// The other package or skin file that still uses it.
+    pub used_by: String,
+}
+
+/// What uninstalling a package removes and keeps.
+#[derive(Debug, Clone)]
+pub struct UninstallPlan {
+    /// The receipt of the package being removed.
+    pub receipt: Receipt,
+    /// User variable files copied to the backup folder first, source and destination.
+    pub backups: Vec<(PathBuf, PathBuf)>,
+    /// The backup generation made for each skin root, by root name.
+    pub generations: Vec<(String, PathBuf)>,
+    /// Seconds since 1970-01-01 UTC.
+    pub created: u64,
+    /// Skin configs, layouts and addons.
+    pub removals: Vec<PathBuf>,
+    /// The package's files in skin roots another package also owns, the root stays.
+    pub files: Vec<PathBuf>,
+    /// Plugins no other package or skin uses.
+    pub plugins: Vec<PathBuf>,
+    /// Plugins that stay installed.
+    pub kept_plugins: Vec<KeptPlugin>,
+    /// The package's copies of its variable files kept for the three-way merge.
+    pub pristine: Vec<PathBuf>,
+    /// Problems after the package was removed, like Rainmeter not starting again.
+    pub warnings: Vec<String>,
+}
+
+impl fmt::Display for UninstallPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Uninstall plan: {}", self.receipt.name)?;
+        if let Some(version) = &self.receipt.version {
+            write!(f, " {}", version)?;
+        }
+        writeln!(f)?;
+        for (source, dest) in &self.backups {
+            writeln!(f, "  backup    {} -> {}", source.display(), dest.display())?;
+        }
+        for removal in self.removals.iter().chain(&self.files) {
+            writeln!(f, "  remove    {}", removal.display())?;
+        }
+        for plugin in &self.plugins {
+            writeln!(f, "  remove    {}", plugin.display())?;
+        }
+        for pristine in &self.pristine {
+            writeln!(f, "  remove    {}", pristine.display())?;
+        }
+        for kept in &self.kept_plugins {
+            writeln!(f, "  keep      {} (used by {})", kept.name, kept.used_by)?;
+        }
+        Ok(())
+    }
+}
+
+/// Removes an installed package by the name it was installed under.
+///
+/// ```no_run
+/// use rainmeter_skin_installer::uninstaller::Uninstaller;
+///
+/// let plan = Uninstaller::new("Suite").backup_variables(true).uninstall()?;
+/// println!("{}", plan);
+/// # Ok::<(), rainmeter_skin_installer::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Uninstaller {
+    package: String,
+    settings: Option<RainmeterSettings>,
+    backup_variables: bool,
+}
+
+impl Uninstaller {
+    /// Uninstalls the package with the receipt named `package`.
+    pub fn new(package: &str) -> Uninstaller {
+        Uninstaller {
+            package: package.to_owned(),
+            settings: None,
+            backup_variables: false,
+        }
+    }
+
+    /// Uses these settings instead of detecting the standard Rainmeter installation.
+    pub fn settings(mut self, settings: RainmeterSettings) -> Uninstaller {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// Copies the package's variable files to the backup folder before removing them.
+    pub fn backup_variables(mut self, backup_variables: bool) -> Uninstaller {
+        self.backup_variables = backup_variables;
+        self
+    }
+
+    /// Works out what would be removed without changing anything. A pinned
+    /// package is refused until it is unpinned.
+    pub fn plan(&self) -> Result<UninstallPlan, Error> {
+        let settings = self.detect_settings()?;
+        let store = ReceiptStore::load(&settings)?;
+        build_plan(&store, &settings, &self.package, self.backup_variables)
+    }
+
+    /// Closes Rainmeter, removes the package and its receipt, and starts
+    /// Rainmeter again if it was running, also when removing fails. Returns
+    /// the plan that was carried out.
+    pub fn uninstall(&self) -> Result<UninstallPlan, Error> {
+        let settings = self.detect_settings()?;
+        let mut store = ReceiptStore::load(&settings)?;
+        let mut plan = build_plan(&store, &settings, &self.package, self.backup_variables)?;
+
+        let mut was_running = false;
+        if !rainmeter::close_rainmeter_if_running(&mut was_running) {
+            return Err(Error::RainmeterRunning);
+        }
+
+        // Rainmeter is started again whether or not everything could be removed
+        let removed = remove_package(&settings, &mut store, &plan);
+
+        if was_running {
+            if let Err(e) = rainmeter::start_rainmeter(&settings, None) {
+                plan.warnings
+                    .push(format!("could not start Rainmeter: {}", e));
+            }
+        }
+        removed?;
+
+        Ok(plan)
+    }
+
+    fn detect_settings(&self) -> Result<RainmeterSettings, Error> {
+        match &self.settings {
+            Some(settings) => Ok(settings.clone()),
+            None => RainmeterSettings::detect(),
+        }
+    }
+}
+
+// carries out the plan with Rainmeter closed, the receipt goes last
+fn remove_package(
+    settings: &RainmeterSettings,
+    store: &mut ReceiptStore,
+    plan: &UninstallPlan,
+) -> Result<(), Error> {
+    for (source, dest) in &plan.backups {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(source, dest)?;
+    }
+    if !plan.generations.is_empty() {
+        let backup_dir = BackupIndex::folder(settings);
+        let mut index = BackupIndex::load(settings)?;
+        for (name, generation) in &plan.generations {
+            index.backups.push(BackupEntry {
+                component: "Skins".to_owned(),
+                name: name.to_owned(),
+                path: generation
+                    .strip_prefix(&backup_dir)
+                    .unwrap_or(generation)
+                    .to_owned(),
+                created: plan.created,
+                version: plan.receipt.version.to_owned(),
+                size: backups::folder_size(generation),
+            });
+        }
+        index.save(settings)?;
+    }
+    for removal in &plan.removals {
+        if removal.is_dir() {
+            fs::remove_dir_all(removal)?;
+        }
+    }
+    for file in &plan.files {
+        if file.is_file() {
+            fs::remove_file(file)?;
+        }
+        remove_empty_parents(file, Path::new(&settings.skins_path));
+    }
+    for plugin in &plan.plugins {
+        if plugin.is_file() {
+            fs::remove_file(plugin)?;
+        }
+    }
+    let pristine_dir = variables::pristine_path(Path::new(&settings.settings_path), Path::new(""));
+    for pristine in &plan.pristine {
+        if pristine.is_file() {
+            fs::remove_file(pristine)?;
+        }
+        remove_empty_parents(pristine, &pristine_dir);
+    }
+
+    store.remove(&plan.receipt.name);
+    store.save(settings)?;
+    Ok(())
+}
+
+fn build_plan(
+    store: &ReceiptStore,
+    settings: &RainmeterSettings,
+    package: &str,
+    backup_variables: bool,
+) -> Result<UninstallPlan, Error> {
+    let receipt = match store.find(package) {
+        Some(receipt) => receipt.clone(),
+        None => return Err(Error::NotInstalled(package.to_owned())),
+    };
+    if receipt.pinned {
+        return Err(Error::Pinned(receipt.name));
+    }
+    let skins_path = Path::new(&settings.skins_path);
+    let settings_path = Path::new(&settings.settings_path);
+
+    let mut plan = UninstallPlan {
+        receipt,
+        backups: vec![],
+        generations: vec![],
+        created: storage::now(),
+        removals: vec![],
+        files: vec![],
+        plugins: vec![],
+        kept_plugins: vec![],
+        pristine: vec![],
+        warnings: vec![],
+    };
+
+    // a new backup generation of each skin root holding only its variable files
+    if backup_variables {
+        let backup_dir = BackupIndex::folder(settings);
+        for file in &plan.receipt.variable_files {
+            let source = skins_path.join(file);
+            let mut parts = file.components();
+            let root = match parts.next() {
+                Some(root) => root.as_os_str().to_string_lossy().into_owned(),
+                None => continue,
+            };
+            if !source.is_file() {
+                continue;
+            }
+            let generation = match plan.generations.iter().find(|(name, _)| *name == root) {
+                Some((_, generation)) => generation.to_owned(),
+                None => {
+                    let generation = backups::generation_path(
+                        &backup_dir.join(&root),
+                        plan.created,
+                        plan.receipt.version.as_deref(),
+                        BackupFormat::Folder,
+                    );
+                    plan.generations.push((root, generation.clone()));
+                    generation
+                }
+            };
+            plan.backups
+                .push((source, generation.join(parts.as_path())));
+        }
+    }
+
+    for component in &plan.receipt.components {
+        match component.component.as_str() {
+            // a MergeSkins package shares its root with the package it adds to
+            "Skins" if is_shared_root(store, &plan.receipt.name, &component.name) => plan
+                .files
+                .extend(component.files.iter().map(|f| f.path.to_owned())),
+            "Skins" => plan.removals.push(skins_path.join(&component.name)),
+            "Layouts" => plan
+                .removals
+                .push(settings_path.join("Layouts").join(&component.name)),
+            "Addons" => plan
+                .removals
+                .push(settings_path.join("Addons").join(&component.name)),
+            _ => (),
+        }
+    }
+
+    // a variable file another package still lists keeps its pristine copy
+    for file in &plan.receipt.variable_files {
+        let shared = store
+            .receipts
+            .iter()
+            .any(|other| other.name != plan.receipt.name && other.variable_files.contains(file));
+        if !shared {
+            plan.pristine
+                .push(variables::pristine_path(settings_path, file));
+        }
+    }
+
+    let references = plugin_references(skins_path, &plan.removals, &plan.files);
+    for component in &plan.receipt.components {
+        if component.component != "Plugins" {
+            continue;
+        }
+        let other = store.receipts.iter().find(|other| {
+            other.name != plan.receipt.name
+                && other.components.iter().any(|c| {
+                    c.component == "Plugins" && c.name.eq_ignore_ascii_case(&component.name)
+                })
+        });
+        let plugin = plugin_name(&component.name);
+        let used_by = match other {
+            Some(other) => Some(other.name.to_owned()),
+            None => references
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(&plugin))
+                .map(|(_, file)| file.display().to_string()),
+        };
+        match used_by {
+            Some(used_by) => plan.kept_plugins.push(KeptPlugin {
+                name: component.name.to_owned(),
+                used_by,
+            }),
+            None => plan
+                .plugins
+                .extend(component.files.iter().map(|f| f.path.to_owned())),
+        }
+    }
+
+    Ok(plan)
+}
+
+// another package installed files below the same skin root
+fn is_shared_root(store: &ReceiptStore, package: &str, root: &str) -> bool {
+    store.receipts.iter().any(|other| {
+        !other.name.eq_ignore_ascii_case(package)
+            && other
+                .components
+                .iter()
+                .any(|c| c.component == "Skins" && c.name.eq_ignore_ascii_case(root))
+    })
+}
+
+// removes the folders below `stop` that removing `file` left empty
+fn remove_empty_parents(file: &Path, stop: &Path) {
+    let mut dir = file.parent();
+    while let Some(path) = dir {
+        if path == stop || !path.starts_with(stop) || fs::remove_dir(path).is_err() {
+            break;
+        }
+        dir = path.parent();
+    }
+}
+
+// Plugin=Name, Plugin=Name.dll or Plugin=Plugins\Name.dll all name the same plugin
+fn plugin_name(value: &str) -> String {
+    let file = value.rsplit(['\\', '/']).next().unwrap_or(value);
+    match file.len().checked_sub(4) {
+        Some(stem) if file[stem..].eq_ignore_ascii_case(".dll") => file[..stem].to_owned(),
+        _ => file.to_owned(),
+    }
+}
+
+// every plugin a skin outside the removed folders uses, with the file using it
+fn plugin_references(
+    skins_path: &Path,
+    removals: &[PathBuf],
+    removed_files: &[PathBuf],
+) -> Vec<(String, PathBuf)> {
+    let backup = skins_path.join("@Backup");
+    let mut references = vec![];
+    for file in plan::list_files(skins_path) {
+        if file.starts_with(&backup)
+            || removals.iter().any(|r| file.starts_with(r))
+            || removed_files.contains(&file)
+        {
+            continue;
+        }
+        let is_skin = match file.extension() {
+            Some(extension) => {
+                extension.eq_ignore_ascii_case("ini") || extension.eq_ignore_ascii_case("inc")
+            }
+            None => false,
+        };
+        if !is_skin {
+            continue;
+        }
+        let ini = match IniFile::read(&file) {
+            Ok(ini) => ini,
+            Err(_) => continue,
+        };
+        for section in ini.sections() {
+            if let Some(plugin) = ini.get(&section, "Plugin") {
+                references.push((plugin_name(&plugin), file.to_owned()));
+            }
+        }
+    }
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conflicts::ModifiedFilePolicy;
+    use crate::receipts::ReceiptComponent;
+    use crate::receipts::ReceiptFile;
+    use crate::receipts::ReceiptPolicy;
+    use crate::variables::VariablePolicy;
+
+    fn receipt(name: &str, files: Vec<PathBuf>) -> Receipt {
+        Receipt {
+            name: name.to_owned(),
+            author: None,
+            version: Some("1.0".to_owned()),
+            archive: PathBuf::from(format!("{}.rmskin", name)),
+            archive_sha256: "00".to_owned(),
+            installed_at: 0,
+            policy: ReceiptPolicy {
+                variables: VariablePolicy::Keep,
+                backup: true,
+                merge_skins: false,
+                force_plugins: false,
+                ignore_requirements: false,
+                allow_addons: false,
+                modified_files: ModifiedFilePolicy::SideCopy,
+            },
+            components: vec![ReceiptComponent {
+                component: "Skins".to_owned(),
+                name: "Suite".to_owned(),
+                files: files
+                    .into_iter()
+                    .map(|path| ReceiptFile {
+                        path,
+                        entry: PathBuf::new(),
+                        size: 0,
+                        sha256: "00".to_owned(),
+                    })
+                    .collect(),
+            }],
+            variable_files: vec![],
+            kept_files: vec![],
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn keeps_skin_roots_other_packages_own() {
+        let skins_path = std::env::temp_dir().join("uninstaller-test-missing-skins");
+        let settings = RainmeterSettings {
+            skins_path: skins_path.to_string_lossy().into_owned(),
+            application_path: String::new(),
+            settings_path: String::new(),
+        };
+        let clock: PathBuf = skins_path.join("Suite").join("Clock").join("Clock.ini");
+        let extra: PathBuf = skins_path.join("Suite").join("Extra").join("Extra.ini");
+        let store = ReceiptStore {
+            format: crate::receipts::FORMAT_VERSION,
+            receipts: vec![
+                receipt("Suite", vec![clock]),
+                receipt("Pack", vec![extra.clone()]),
+            ],
+        };
+
+        let plan = build_plan(&store, &settings, "pack", false).unwrap();
+        assert!(plan.removals.is_empty());
+        assert_eq!(plan.files, vec![extra]);
+
+        let store = ReceiptStore {
+            format: crate::receipts::FORMAT_VERSION,
+            receipts: vec![store.receipts[0].clone()],
+        };
+        let plan = build_plan(&store, &settings, "Suite", false).unwrap();
+        assert_eq!(plan.removals, vec![skins_path.join("Suite")]);
+        assert!(plan.files.is_empty());
+    }
+
+    #[test]
+    fn plans_pristine_removal_and_refuses_pinned() {
+        let settings = RainmeterSettings {
+            skins_path: String::new(),
+            application_path: String::new(),
+            settings_path: "Settings".to_owned(),
+        };
+        let mut suite = receipt("Suite", vec![]);
+        suite.variable_files = vec![PathBuf::from("Suite").join("Variables.inc")];
+        let mut store = ReceiptStore {
+            format: crate::receipts::FORMAT_VERSION,
+            receipts: vec![suite],
+        };
+
+        let plan = build_plan(&store, &settings, "Suite", false).unwrap();
+        let pristine: PathBuf = [
+            "Settings",
+            "SkinInstaller",
+            "Pristine",
+            "Suite",
+            "Variables.inc",
+        ]
+        .iter()
+        .collect();
+        assert_eq!(plan.pristine, vec![pristine]);
+
+        store.set_pinned("Suite", true).unwrap();
+        assert!(matches!(
+            build_plan(&store, &settings, "Suite", false),
+            Err(Error::Pinned(_))
+        ));
+    }
+
+    #[test]
+    fn names_plugins_like_rainmeter() {
+        assert_eq!(plugin_name("AudioLevel"), "AudioLevel");
+        assert_eq!(plugin_name("AudioLevel.dll"), "AudioLevel");
+        assert_eq!(plugin_name("Plugins\\AudioLevel.DLL"), "AudioLevel");
+    }
+}
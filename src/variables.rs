@@ -0,0 +1,647 @@
+// resolves the VariableFiles entries of RMSKIN.ini to concrete files
+//
+// an entry is relative to the Skins folder and can be
+// - a file: Suite\@Resources\Variables.inc
+// - a glob: Suite\@Resources\Settings\*.inc, * and ? stay within a folder, ** spans folders
+// - a folder: Suite\@Resources\Settings, meaning every .ini and .inc below it
+// names are compared case-insensitively like Windows does, and only the skin
+// folders the package ships are searched for installed files
+
+use crate::archive::PackageArchive;
+use crate::ini::IniFile;
+use crate::plan;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// extensions a folder entry picks up, anything else is not a variable file
+const VARIABLE_EXTENSIONS: [&str; 2] = ["ini", "inc"];
+
+/// The files one VariableFiles pattern matches.
+#[derive(Debug, Clone)]
+pub struct PatternMatch {
+    /// As written in RMSKIN.ini.
+    pub pattern: String,
+    /// Files matched in the installed skins, relative to the Skins folder.
+    pub old: Vec<PathBuf>,
+    /// Files matched in the package, relative to its Skins folder.
+    pub new: Vec<PathBuf>,
+}
+
+impl fmt::Display for PatternMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern)?;
+        if self.old.is_empty() && self.new.is_empty() {
+            return write!(f, " matched nothing");
+        }
+        for (tree, files) in [("installed", &self.old), ("package", &self.new)] {
+            write!(f, "\n    {}: ", tree)?;
+            if files.is_empty() {
+                write!(f, "none")?;
+            }
+            let files: Vec<String> = files.iter().map(|p| p.display().to_string()).collect();
+            write!(f, "{}", files.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+struct Pattern {
+    components: Vec<String>,
+    is_glob: bool,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Pattern {
+        let components: Vec<String> = pattern
+            .split(['\\', '/'])
+            .filter(|c| !c.is_empty() && *c != ".")
+            .map(|c| c.to_owned())
+            .collect();
+        let is_glob = components.iter().any(|c| c.contains(['*', '?']));
+        Pattern {
+            components,
+            is_glob,
+        }
+    }
+
+    // the folders before the first wildcard, the only part worth walking
+    fn literal_prefix(&self) -> PathBuf {
+        self.components
+            .iter()
+            .take_while(|c| !c.contains(['*', '?']))
+            .collect()
+    }
+
+    // whether the path is somewhere below the folder the pattern names
+    fn is_below(&self, path: &Path) -> bool {
+        let path = path_components(path);
+        path.len() > self.components.len()
+            && match_components(&self.components, &path[..self.components.len()])
+    }
+
+    fn matches(&self, path: &Path, as_folder: bool) -> bool {
+        if as_folder {
+            return self.is_below(path) && has_variable_extension(path);
+        }
+        match_components(&self.components, &path_components(path))
+    }
+}
+
+// matches every VariableFiles entry against the installed skins and the package
+pub(crate) fn match_variable_files(
+    patterns: &[String],
+    skins_path: &Path,
+    archive: &PackageArchive,
+) -> Vec<PatternMatch> {
+    // package files relative to its Skins folder
+    let new_files: Vec<PathBuf> = archive
+        .entries
+        .iter()
+        .filter(|entry| !entry.is_dir)
+        .filter_map(|entry| entry.path.strip_prefix("Skins").ok())
+        .map(|path| path.to_owned())
+        .collect();
+
+    // only the skin roots the package ships are searched, never another package's
+    // skins or the backup generations in @Backup
+    let mut roots: Vec<String> = vec![];
+    for root in new_files
+        .iter()
+        .filter_map(|file| path_components(file).into_iter().next())
+    {
+        if !root.starts_with('@') && !roots.iter().any(|r| r.eq_ignore_ascii_case(&root)) {
+            roots.push(root);
+        }
+    }
+    let in_roots = |path: &Path| match path_components(path).first() {
+        Some(root) => roots.iter().any(|r| r.eq_ignore_ascii_case(root)),
+        None => false,
+    };
+
+    patterns
+        .iter()
+        .map(|pattern| {
+            let parsed = Pattern::parse(pattern);
+            let prefix = parsed.literal_prefix();
+
+            let installed = skins_path.join(&prefix);
+            let folders: Vec<PathBuf> = if prefix.as_os_str().is_empty() {
+                roots.iter().map(|root| skins_path.join(root)).collect()
+            } else {
+                vec![installed.to_owned()]
+            };
+            let old_files: Vec<PathBuf> = if installed.is_file() {
+                vec![prefix]
+            } else {
+                folders
+                    .iter()
+                    .flat_map(|folder| plan::list_files(folder))
+                    .filter_map(|path| path.strip_prefix(skins_path).ok().map(|p| p.to_owned()))
+                    .collect()
+            }
+            .into_iter()
+            .filter(|file| in_roots(file))
+            .collect();
+
+            // an entry without wildcards names a folder if it is one in either tree
+            let as_folder = !parsed.is_glob
+                && (installed.is_dir() || new_files.iter().any(|file| parsed.is_below(file)));
+
+            let select = |files: &[PathBuf]| -> Vec<PathBuf> {
+                let mut matched: Vec<PathBuf> = files
+                    .iter()
+                    .filter(|file| parsed.matches(file, as_folder))
+                    .cloned()
+                    .collect();
+                matched.sort();
+                matched
+            };
+
+            PatternMatch {
+                pattern: pattern.to_owned(),
+                old: select(&old_files),
+                new: select(&new_files),
+            }
+        })
+        .collect()
+}
+
+/// What happens to the user's values in the VariableFiles, the same whether
+/// the package merges or replaces its skins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VariablePolicy {
+    /// Every key of the installed files is written to the new files.
+    #[default]
+    Keep,
+    /// Nothing is carried over, the new files keep the package defaults.
+    Reset,
+    /// Only keys the new version still has are carried over.
+    KeepExistingKeysOnly,
+    /// Like keep, but the user is asked for each file.
+    PromptFile,
+}
+
+impl FromStr for VariablePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "keep" => Ok(VariablePolicy::Keep),
+            "reset" => Ok(VariablePolicy::Reset),
+            "keep-existing-keys-only" => Ok(VariablePolicy::KeepExistingKeysOnly),
+            "prompt-file" => Ok(VariablePolicy::PromptFile),
+            _ => Err(format!(
+                "\"{}\" is not one of keep, reset, keep-existing-keys-only, prompt-file",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for VariablePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VariablePolicy::Keep => write!(f, "keep"),
+            VariablePolicy::Reset => write!(f, "reset"),
+            VariablePolicy::KeepExistingKeysOnly => write!(f, "keep-existing-keys-only"),
+            VariablePolicy::PromptFile => write!(f, "prompt-file"),
+        }
+    }
+}
+
+/// The section carried over when neither RMSKIN.ini nor the caller names any.
+pub const DEFAULT_SECTION: &str = "Variables";
+
+/// The sections of a variable file matching the VariableSections entries,
+/// "*" keeps every section and the other wildcards work like in VariableFiles.
+pub fn select_sections(ini: &IniFile, patterns: &[String]) -> Vec<String> {
+    ini.sections()
+        .into_iter()
+        .filter(|section| patterns.iter().any(|pattern| match_name(pattern, section)))
+        .collect()
+}
+
+/// A key whose value in the installed file differs from the package default.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedValue {
+    /// Key name.
+    pub key: String,
+    /// The user's value, the one that is kept.
+    pub old: String,
+    /// The package default it replaces.
+    pub new: String,
+}
+
+/// A key and its value.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyValue {
+    /// Key name.
+    pub key: String,
+    /// Value.
+    pub value: String,
+}
+
+/// What carrying one variable file over does to its keys.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VariableReport {
+    /// Relative to the Skins folder.
+    pub file: PathBuf,
+    /// The section the keys were read from.
+    pub section: String,
+    /// In both files with the same value.
+    pub carried: Vec<String>,
+    /// In both files, the user's value replaces the package default.
+    pub changed: Vec<ChangedValue>,
+    /// In both files but the user never changed the value, the new package default
+    /// replaces the old one.
+    pub updated: Vec<ChangedValue>,
+    /// Only in the installed file, the new version doesn't use them.
+    pub obsolete: Vec<KeyValue>,
+    /// Only in the package, they keep the package default.
+    pub added: Vec<KeyValue>,
+}
+
+impl VariableReport {
+    /// Old and new are the entries of the installed file and of the package's file,
+    /// pristine those of the file as the previous package shipped it, empty if unknown.
+    pub fn compare(
+        file: &Path,
+        section: &str,
+        old: &[(String, String)],
+        new: &[(String, String)],
+        pristine: &[(String, String)],
+    ) -> VariableReport {
+        let find = |entries: &[(String, String)], key: &str| {
+            entries
+                .iter()
+                .find(|(k, _)| same_key(k, key))
+                .map(|(_, v)| v.to_owned())
+        };
+
+        let mut report = VariableReport {
+            file: file.to_owned(),
+            section: section.to_owned(),
+            ..Default::default()
+        };
+        for (key, new_value) in new {
+            match find(old, key) {
+                Some(old_value) if old_value == *new_value => report.carried.push(key.to_owned()),
+                Some(old_value) if find(pristine, key).as_ref() == Some(&old_value) => {
+                    report.updated.push(ChangedValue {
+                        key: key.to_owned(),
+                        old: old_value,
+                        new: new_value.to_owned(),
+                    })
+                }
+                Some(old_value) => report.changed.push(ChangedValue {
+                    key: key.to_owned(),
+                    old: old_value,
+                    new: new_value.to_owned(),
+                }),
+                None => report.added.push(KeyValue {
+                    key: key.to_owned(),
+                    value: new_value.to_owned(),
+                }),
+            }
+        }
+        for (key, old_value) in old {
+            if find(new, key).is_none() {
+                report.obsolete.push(KeyValue {
+                    key: key.to_owned(),
+                    value: old_value.to_owned(),
+                });
+            }
+        }
+        report
+    }
+
+    /// Drops the installed values that aren't the user's to keep: those the new package
+    /// default replaces and, when asked, those the new version no longer uses. The keys
+    /// in the report are spelled as in the package, so they match case-insensitively.
+    pub fn retain_user_values(&self, values: &mut Vec<(String, String)>, drop_obsolete: bool) {
+        values.retain(|(key, _)| {
+            let updated = self.updated.iter().any(|u| same_key(&u.key, key));
+            let obsolete = self.obsolete.iter().any(|o| same_key(&o.key, key));
+            !(updated || drop_obsolete && obsolete)
+        });
+    }
+}
+
+// ini keys match case-insensitively
+fn same_key(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b) || a.to_lowercase() == b.to_lowercase()
+}
+
+impl fmt::Display for VariableReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [{}]: {} carried, {} changed, {} updated, {} obsolete, {} new",
+            self.file.display(),
+            self.section,
+            self.carried.len(),
+            self.changed.len(),
+            self.updated.len(),
+            self.obsolete.len(),
+            self.added.len()
+        )?;
+        if !self.carried.is_empty() {
+            write!(f, "\n    carried   {}", self.carried.join(", "))?;
+        }
+        for changed in &self.changed {
+            write!(
+                f,
+                "\n    changed   {}={} (package default {})",
+                changed.key, changed.old, changed.new
+            )?;
+        }
+        for updated in &self.updated {
+            write!(
+                f,
+                "\n    updated   {}={} (old default {})",
+                updated.key, updated.new, updated.old
+            )?;
+        }
+        for obsolete in &self.obsolete {
+            write!(f, "\n    obsolete  {}={}", obsolete.key, obsolete.value)?;
+        }
+        for added in &self.added {
+            write!(f, "\n    new       {}={}", added.key, added.value)?;
+        }
+        Ok(())
+    }
+}
+
+// where the package's copy of an installed variable file is kept for the next
+// upgrade, so values the user never touched can be told apart from their own
+pub(crate) fn pristine_path(settings_path: &Path, file: &Path) -> PathBuf {
+    settings_path
+        .join("SkinInstaller")
+        .join("Pristine")
+        .join(file)
+}
+
+// the previous package's copy of a variable file, none if it wasn't kept
+pub(crate) fn pristine_file(settings_path: &Path, file: &Path) -> std::io::Result<Option<IniFile>> {
+    let path = pristine_path(settings_path, file);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    IniFile::read(&path).map(Some)
+}
+
+// the package's copy of a variable file, none if it doesn't ship one
+pub(crate) fn package_file(
+    archive: &mut PackageArchive,
+    file: &Path,
+) -> std::io::Result<Option<IniFile>> {
+    let path = Path::new("Skins").join(file);
+    let path = path.to_string_lossy().to_lowercase();
+    let entry = archive
+        .entries
+        .iter()
+        .find(|entry| !entry.is_dir && entry.path.to_string_lossy().to_lowercase() == path)
+        .cloned();
+    match entry {
+        Some(entry) => Ok(Some(IniFile::from_bytes(&archive.read(&entry)?))),
+        None => Ok(None),
+    }
+}
+
+fn path_components(path: &Path) -> Vec<String> {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect()
+}
+
+fn has_variable_extension(path: &Path) -> bool {
+    match path.extension() {
+        Some(extension) => VARIABLE_EXTENSIONS
+            .iter()
+            .any(|e| extension.eq_ignore_ascii_case(e)),
+        None => false,
+    }
+}
+
+fn match_components(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_components(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path_rest)) => match_name(first, name) && match_components(rest, path_rest),
+            None => false,
+        },
+    }
+}
+
+fn match_name(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    match_chars(&pattern, &name)
+}
+
+fn match_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| match_chars(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && match_chars(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && match_chars(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn path(path: &str) -> PathBuf {
+        path.split('\\').collect()
+    }
+
+    fn entries(entries: &[(&str, &str)]) -> Vec<(String, String)> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn matches_files_globs_and_folders() {
+        let file = Pattern::parse("Suite\\@Resources\\Variables.inc");
+        assert!(file.matches(&path("suite\\@resources\\variables.INC"), false));
+        assert!(!file.matches(&path("Suite\\@Resources\\Other.inc"), false));
+
+        let glob = Pattern::parse("Suite/@Resources/Settings/*.inc");
+        assert!(glob.matches(&path("Suite\\@Resources\\Settings\\Clock.inc"), false));
+        assert!(!glob.matches(&path("Suite\\@Resources\\Settings\\Sub\\Clock.inc"), false));
+        assert!(!glob.matches(&path("Suite\\@Resources\\Settings\\Clock.ini"), false));
+
+        let deep = Pattern::parse("Suite\\**\\Widget?.inc");
+        assert!(deep.matches(&path("Suite\\Widget1.inc"), false));
+        assert!(deep.matches(&path("Suite\\A\\B\\Widget2.inc"), false));
+        assert!(!deep.matches(&path("Suite\\A\\Widget10.inc"), false));
+
+        let folder = Pattern::parse("Suite\\@Resources\\Settings\\");
+        assert!(folder.matches(&path("Suite\\@Resources\\Settings\\Sub\\Clock.ini"), true));
+        assert!(!folder.matches(&path("Suite\\@Resources\\Settings\\Logo.png"), true));
+        assert!(!folder.matches(&path("Suite\\@Resources\\Settings"), true));
+    }
+
+    #[test]
+    fn matches_only_the_package_roots() {
+        let folder = std::env::temp_dir().join(format!("variables-test-{}", std::process::id()));
+        let skins_path = folder.join("Skins");
+        for root in [
+            path("Suite\\@Resources"),
+            path("@Backup\\Suite\\20240131-184502-1.0\\@Resources"),
+            path("Other\\@Resources"),
+        ] {
+            let resources = skins_path.join(root);
+            fs::create_dir_all(&resources).unwrap();
+            fs::write(resources.join("Variables.inc"), "[Variables]").unwrap();
+        }
+        let archive = crate::plan::tests::package(
+            &folder.join("Suite.rmskin"),
+            &[("Skins/Suite/@Resources/Variables.inc", "[Variables]")],
+        );
+
+        let patterns = [
+            "**\\Variables.inc".to_owned(),
+            "Other\\@Resources".to_owned(),
+        ];
+        let matches = match_variable_files(&patterns, &skins_path, &archive);
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(
+            matches[0].old,
+            vec![path("Suite\\@Resources\\Variables.inc")]
+        );
+        assert_eq!(
+            matches[0].new,
+            vec![path("Suite\\@Resources\\Variables.inc")]
+        );
+        assert!(matches[1].old.is_empty());
+    }
+
+    #[test]
+    fn selects_sections() {
+        let ini = IniFile::parse(
+            "[Variables]\nA=1\n[MeterStyleTitle]\nX=1\n[MeterStyleBody]\nX=2\n[Metadata]\nName=Suite\n",
+            crate::ini::Encoding::Utf8,
+        );
+        let sections = |patterns: &[&str]| {
+            let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+            select_sections(&ini, &patterns)
+        };
+        assert_eq!(sections(&["variables"]), vec!["Variables"]);
+        assert_eq!(
+            sections(&["Variables", "MeterStyle*"]),
+            vec!["Variables", "MeterStyleTitle", "MeterStyleBody"]
+        );
+        assert_eq!(sections(&["*"]).len(), 4);
+        assert!(sections(&["Missing"]).is_empty());
+    }
+
+    #[test]
+    fn reports_carried_changed_obsolete_and_new() {
+        let old = entries(&[
+            ("Color", "255,0,0"),
+            ("FontFace", "Arial"),
+            ("OldWidth", "200"),
+        ]);
+        let new = entries(&[
+            ("fontface", "Arial"),
+            ("Color", "255,255,255"),
+            ("Width", "100"),
+        ]);
+
+        let report = VariableReport::compare(
+            Path::new("Suite\\Variables.inc"),
+            "Variables",
+            &old,
+            &new,
+            &[],
+        );
+        assert_eq!(report.carried, vec!["fontface"]);
+        assert_eq!(report.changed.len(), 1);
+        assert_eq!(
+            (
+                report.changed[0].old.as_str(),
+                report.changed[0].new.as_str()
+            ),
+            ("255,0,0", "255,255,255")
+        );
+        assert_eq!(report.obsolete[0].key, "OldWidth");
+        assert_eq!(report.added[0].key, "Width");
+    }
+
+    #[test]
+    fn takes_new_defaults_the_user_never_changed() {
+        let pristine = entries(&[("FontFace", "Arial"), ("Color", "255,255,255")]);
+        let old = entries(&[("FontFace", "Arial"), ("Color", "255,0,0")]);
+        let new = entries(&[("FontFace", "Segoe UI"), ("Color", "200,200,200")]);
+
+        let report = VariableReport::compare(
+            Path::new("Suite\\Variables.inc"),
+            "Variables",
+            &old,
+            &new,
+            &pristine,
+        );
+        assert_eq!(report.updated.len(), 1);
+        assert_eq!(report.updated[0].key, "FontFace");
+        assert_eq!(report.updated[0].new, "Segoe UI");
+        assert_eq!(report.changed.len(), 1);
+        assert_eq!(report.changed[0].key, "Color");
+    }
+
+    #[test]
+    fn drops_updated_values_whatever_their_case() {
+        let pristine = entries(&[("FontFace", "Arial"), ("OldWidth", "100")]);
+        let old = entries(&[
+            ("fontface", "Arial"),
+            ("Color", "255,0,0"),
+            ("oldwidth", "200"),
+        ]);
+        let new = entries(&[("FontFace", "Segoe UI"), ("COLOR", "200,200,200")]);
+
+        let report = VariableReport::compare(
+            Path::new("Suite\\Variables.inc"),
+            "Variables",
+            &old,
+            &new,
+            &pristine,
+        );
+        assert_eq!(report.updated[0].key, "FontFace");
+
+        let mut values = old.clone();
+        report.retain_user_values(&mut values, false);
+        assert_eq!(
+            values,
+            entries(&[("Color", "255,0,0"), ("oldwidth", "200")])
+        );
+        report.retain_user_values(&mut values, true);
+        assert_eq!(values, entries(&[("Color", "255,0,0")]));
+    }
+
+    #[test]
+    fn parses_variable_policies() {
+        for policy in [
+            VariablePolicy::Keep,
+            VariablePolicy::Reset,
+            VariablePolicy::KeepExistingKeysOnly,
+            VariablePolicy::PromptFile,
+        ] {
+            assert_eq!(policy.to_string().parse::<VariablePolicy>(), Ok(policy));
+        }
+        assert_eq!("Reset".parse::<VariablePolicy>(), Ok(VariablePolicy::Reset));
+        assert!("merge".parse::<VariablePolicy>().is_err());
+    }
+}
@@ -0,0 +1,423 @@
+// checks an installed package against its receipt and restores what went
+// missing or got corrupted from the package it was installed from
+
+use crate::archive::ArchiveEntry;
+use crate::archive::PackageArchive;
+use crate::plan;
+use crate::rainmeter;
+use crate::receipts;
+use crate::receipts::Receipt;
+use crate::receipts::ReceiptStore;
+use crate::Error;
+use crate::RainmeterSettings;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// What changed in one skin config, layout, plugin or addon since it was installed.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ComponentStatus {
+    /// Skins, Layouts, Plugins or Addons.
+    pub component: String,
+    /// The skin root, layout, plugin or addon name.
+    pub name: String,
+    /// Recorded files that still match.
+    pub intact: usize,
+    /// Recorded files that are gone.
+    pub missing: Vec<PathBuf>,
+    /// Recorded files whose hash changed.
+    pub modified: Vec<PathBuf>,
+    /// Variable files and files kept by the modified files policy that the user
+    /// changed, expected and left alone by a repair.
+    pub changed_by_user: Vec<PathBuf>,
+    /// Files in the folder that the package didn't write.
+    pub extra: Vec<PathBuf>,
+}
+
+impl ComponentStatus {
+    /// No recorded file is missing or modified.
+    pub fn is_intact(&self) -> bool {
+        self.missing.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// An installed package checked against its receipt.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    /// Package name.
+    pub package: String,
+    /// Installed version.
+    pub version: Option<String>,
+    /// One for each component of the receipt.
+    pub components: Vec<ComponentStatus>,
+}
+
+impl VerifyReport {
+    /// No recorded file is missing or modified, changed variables and extra files don't count.
+    pub fn is_intact(&self) -> bool {
+        self.components.iter().all(|c| c.is_intact())
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.package)?;
+        if let Some(version) = &self.version {
+            write!(f, " {}", version)?;
+        }
+        write!(
+            f,
+            ": {}",
+            if self.is_intact() {
+                "intact"
+            } else {
+                "damaged"
+            }
+        )?;
+        for component in &self.components {
+            write!(
+                f,
+                "\n  {} {}: {} intact, {} missing, {} modified, {} changed by the user, {} extra",
+                component.component,
+                component.name,
+                component.intact,
+                component.missing.len(),
+                component.modified.len(),
+                component.changed_by_user.len(),
+                component.extra.len()
+            )?;
+            for (status, files) in [
+                ("missing", &component.missing),
+                ("modified", &component.modified),
+                ("user", &component.changed_by_user),
+                ("extra", &component.extra),
+            ] {
+                for file in files {
+                    write!(f, "\n    {:<9} {}", status, file.display())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A missing or modified file put back, or why it wasn't.
+#[derive(Debug, Clone)]
+pub struct RepairedFile {
+    /// The file.
+    pub path: PathBuf,
+    /// Why it wasn't restored.
+    pub error: Option<String>,
+}
+
+impl fmt::Display for RepairedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            None => write!(f, "restored  {}", self.path.display()),
+            Some(error) => write!(f, "failed    {}: {}", self.path.display(), error),
+        }
+    }
+}
+
+/// Re-hashes every file the installed package `package` wrote.
+pub fn verify(settings: &RainmeterSettings, package: &str) -> Result<VerifyReport, Error> {
+    let store = ReceiptStore::load(settings)?;
+    match store.find(package) {
+        Some(receipt) => verify_receipt(settings, receipt),
+        None => Err(Error::NotInstalled(package.to_owned())),
+    }
+}
+
+/// Restores the missing and modified files of the package from `archive`, which must be
+/// the package it was installed from. Variable files the user changed are left alone.
+/// Rainmeter failing to start again afterwards is added to `warnings`.
+pub fn repair(
+    settings: &RainmeterSettings,
+    package: &str,
+    archive: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<RepairedFile>, Error> {
+    let store = ReceiptStore::load(settings)?;
+    let receipt = match store.find(package) {
+        Some(receipt) => receipt,
+        None => return Err(Error::NotInstalled(package.to_owned())),
+    };
+    if receipts::sha256_file(archive)? != receipt.archive_sha256 {
+        return Err(Error::WrongArchive(receipt.name.to_owned()));
+    }
+
+    let report = verify_receipt(settings, receipt)?;
+    let skins_path = Path::new(&settings.skins_path);
+    let is_user_file = |path: &Path| is_user_file(receipt, skins_path, path);
+    // a variable file holds the user's settings, even a missing one isn't put back
+    let damaged: Vec<&PathBuf> = report
+        .components
+        .iter()
+        .flat_map(|c| c.missing.iter().chain(&c.modified))
+        .filter(|path| !is_user_file(path))
+        .collect();
+    if damaged.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut package_archive = PackageArchive::open(archive)?;
+    // plugins can't be replaced while Rainmeter has them loaded
+    let mut was_running = false;
+    if !rainmeter::close_rainmeter_if_running(&mut was_running) {
+        return Err(Error::RainmeterRunning);
+    }
+
+    let mut repaired = vec![];
+    for path in damaged {
+        let recorded = receipt.files().find(|f| f.path == *path).unwrap();
+        // a failure is recorded rather than returned so Rainmeter still gets restarted
+        let error = match package_archive.find(&recorded.entry) {
+            Some(entry) => restore(&mut package_archive, &entry, path, &recorded.sha256).err(),
+            None => Some("not found in the package".to_owned()),
+        };
+        repaired.push(RepairedFile {
+            path: path.to_owned(),
+            error,
+        });
+    }
+
+    if was_running {
+        if let Err(e) = rainmeter::start_rainmeter(settings, None) {
+            warnings.push(format!("could not start Rainmeter: {}", e));
+        }
+    }
+
+    Ok(repaired)
+}
+
+// the package's copy is checked against the receipt before it replaces anything,
+// so a copy that doesn't match leaves the file as it was
+fn restore(
+    archive: &mut PackageArchive,
+    entry: &ArchiveEntry,
+    path: &Path,
+    sha256: &str,
+) -> Result<(), String> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".repair.tmp");
+    let temp = PathBuf::from(temp);
+    let restored = archive
+        .extract(entry, &temp)
+        .and_then(|_| receipts::sha256_file(&temp))
+        .map_err(|e| e.to_string())
+        .and_then(|hash| {
+            if hash == sha256 {
+                fs::rename(&temp, path).map_err(|e| e.to_string())
+            } else {
+                Err("the package's copy doesn't match the receipt".to_owned())
+            }
+        });
+    if restored.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    restored
+}
+
+fn verify_receipt(settings: &RainmeterSettings, receipt: &Receipt) -> Result<VerifyReport, Error> {
+    let skins_path = Path::new(&settings.skins_path);
+    let settings_path = Path::new(&settings.settings_path);
+    let is_user_file = |path: &Path| is_user_file(receipt, skins_path, path);
+
+    let mut components = vec![];
+    for component in &receipt.components {
+        let mut status = ComponentStatus {
+            component: component.component.to_owned(),
+            name: component.name.to_owned(),
+            ..Default::default()
+        };
+
+        for file in &component.files {
+            if !file.path.is_file() {
+                status.missing.push(file.path.to_owned());
+                continue;
+            }
+            let unchanged = fs::metadata(&file.path)?.len() == file.size
+                && receipts::sha256_file(&file.path)? == file.sha256;
+            if unchanged {
+                status.intact += 1;
+            } else if is_user_file(&file.path) {
+                status.changed_by_user.push(file.path.to_owned());
+            } else {
+                status.modified.push(file.path.to_owned());
+            }
+        }
+
+        // plugins are single files, the other components own their whole folder
+        let folder = match component.component.as_str() {
+            "Skins" => Some(skins_path.join(&component.name)),
+            "Layouts" => Some(settings_path.join("Layouts").join(&component.name)),
+            "Addons" => Some(settings_path.join("Addons").join(&component.name)),
+            _ => None,
+        };
+        if let Some(folder) = folder {
+            let mut extra: Vec<PathBuf> = plan::list_files(&folder)
+                .into_iter()
+                .filter(|path| !component.files.iter().any(|f| same_path(&f.path, path)))
+                .collect();
+            extra.sort();
+            status.extra = extra;
+        }
+
+        components.push(status);
+    }
+
+    Ok(VerifyReport {
+        package: receipt.name.to_owned(),
+        version: receipt.version.to_owned(),
+        components,
+    })
+}
+
+// the package's variable files and the files the user kept over the package's
+fn is_user_file(receipt: &Receipt, skins_path: &Path, path: &Path) -> bool {
+    receipt
+        .variable_files
+        .iter()
+        .any(|file| same_path(&skins_path.join(file), path))
+        || receipt.kept_files.iter().any(|file| same_path(file, path))
+}
+
+// names are compared case-insensitively like Windows does
+fn same_path(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conflicts::ModifiedFilePolicy;
+    use crate::receipts::ReceiptComponent;
+    use crate::receipts::ReceiptFile;
+    use crate::receipts::ReceiptPolicy;
+    use crate::variables::VariablePolicy;
+
+    fn receipt(files: Vec<ReceiptFile>) -> Receipt {
+        Receipt {
+            name: "Suite".to_owned(),
+            author: None,
+            version: Some("1.0".to_owned()),
+            archive: PathBuf::from("Suite.rmskin"),
+            archive_sha256: "00".to_owned(),
+            installed_at: 0,
+            policy: ReceiptPolicy {
+                variables: VariablePolicy::Keep,
+                backup: true,
+                merge_skins: false,
+                force_plugins: false,
+                ignore_requirements: false,
+                allow_addons: false,
+                modified_files: ModifiedFilePolicy::Keep,
+            },
+            components: vec![ReceiptComponent {
+                component: "Skins".to_owned(),
+                name: "Suite".to_owned(),
+                files,
+            }],
+            variable_files: vec![PathBuf::from("Suite").join("Variables.inc")],
+            kept_files: vec![],
+            pinned: false,
+        }
+    }
+
+    // writes the file as the package installed it and records it
+    fn install(path: &Path, content: &str) -> ReceiptFile {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        ReceiptFile {
+            path: path.to_owned(),
+            entry: PathBuf::new(),
+            size: content.len() as u64,
+            sha256: receipts::sha256_file(path).unwrap(),
+        }
+    }
+
+    #[test]
+    fn tells_user_files_apart() {
+        let skins_path = Path::new("Skins");
+        let mut receipt = receipt(vec![]);
+        receipt.kept_files = vec![skins_path.join("Suite").join("Clock.ini")];
+
+        assert!(is_user_file(
+            &receipt,
+            skins_path,
+            &skins_path.join("suite").join("VARIABLES.inc")
+        ));
+        assert!(is_user_file(
+            &receipt,
+            skins_path,
+            &skins_path.join("Suite").join("clock.ini")
+        ));
+        assert!(!is_user_file(
+            &receipt,
+            skins_path,
+            &skins_path.join("Suite").join("Other.ini")
+        ));
+    }
+
+    #[test]
+    fn reports_missing_modified_user_and_extra_files() {
+        let skins_path = std::env::temp_dir().join(format!("verify-test-{}", std::process::id()));
+        let settings = RainmeterSettings {
+            skins_path: skins_path.to_string_lossy().into_owned(),
+            application_path: String::new(),
+            settings_path: String::new(),
+        };
+        let suite = skins_path.join("Suite");
+        let files = vec![
+            install(&suite.join("Clock.ini"), "[Clock]"),
+            install(&suite.join("Gone.ini"), "[Gone]"),
+            install(&suite.join("Broken.ini"), "[Broken]"),
+            install(&suite.join("Variables.inc"), "[Variables]\nColor=1"),
+        ];
+        fs::remove_file(suite.join("Gone.ini")).unwrap();
+        fs::write(suite.join("Broken.ini"), "[Brokem]").unwrap();
+        fs::write(suite.join("Variables.inc"), "[Variables]\nColor=2").unwrap();
+        fs::write(suite.join("Notes.txt"), "mine").unwrap();
+
+        let report = verify_receipt(&settings, &receipt(files));
+        fs::remove_dir_all(&skins_path).unwrap();
+        let report = report.unwrap();
+
+        let status = &report.components[0];
+        assert_eq!(status.intact, 1);
+        assert_eq!(status.missing, vec![suite.join("Gone.ini")]);
+        assert_eq!(status.modified, vec![suite.join("Broken.ini")]);
+        assert_eq!(status.changed_by_user, vec![suite.join("Variables.inc")]);
+        assert_eq!(status.extra, vec![suite.join("Notes.txt")]);
+        assert!(!report.is_intact());
+    }
+
+    #[test]
+    fn restores_only_a_copy_matching_the_receipt() {
+        let temp = std::env::temp_dir().join(format!("verify-repair-test-{}", std::process::id()));
+        fs::create_dir_all(&temp).unwrap();
+        let mut archive = crate::plan::tests::package(
+            &temp.join("Suite.rmskin"),
+            &[("Skins/Suite/Clock.ini", "[Clock]")],
+        );
+        let entry = archive.entries[0].clone();
+        let clock = temp.join("Clock.ini");
+        let recorded = install(&clock, "[Clock]").sha256;
+
+        fs::write(&clock, "[Clokc]").unwrap();
+        let mismatch = restore(&mut archive, &entry, &clock, "00");
+        let kept = fs::read_to_string(&clock).unwrap();
+        let leftover = temp.join("Clock.ini.repair.tmp").exists();
+        let matched = restore(&mut archive, &entry, &clock, &recorded);
+        let restored = fs::read_to_string(&clock).unwrap();
+        fs::remove_dir_all(&temp).unwrap();
+
+        assert!(mismatch.is_err());
+        assert_eq!(kept, "[Clokc]");
+        assert!(!leftover);
+        assert_eq!(matched, Ok(()));
+        assert_eq!(restored, "[Clock]");
+    }
+}
@@ -0,0 +1,200 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Dotted numeric version as used by MinimumRainmeter ("4.5.0.3700") and MinimumWindows ("6.1"),
+/// missing trailing parts compare as 0, so "4.5" == "4.5.0.0".
+#[derive(Debug, Clone, Default)]
+pub struct DottedVersion(pub Vec<u32>);
+
+impl DottedVersion {
+    /// Whether every part is 0, as a version resource without a version reads.
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|p| *p == 0)
+    }
+
+    fn part(&self, i: usize) -> u32 {
+        self.0.get(i).copied().unwrap_or(0)
+    }
+}
+
+impl FromStr for DottedVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .trim()
+            .split('.')
+            .map(|p| p.trim().parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| ())?;
+        if parts.is_empty() {
+            return Err(());
+        }
+        Ok(DottedVersion(parts))
+    }
+}
+
+impl fmt::Display for DottedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = self.0.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        write!(f, "{}", parts.join("."))
+    }
+}
+
+impl Ord for DottedVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = std::cmp::max(self.0.len(), other.0.len());
+        (0..len)
+            .map(|i| self.part(i).cmp(&other.part(i)))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for DottedVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for DottedVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DottedVersion {}
+
+/// A package Version as authors write them: "1.2", "v1.2.3", "1.2.3-beta.2", "2.0 RC1".
+/// The leading dotted numbers compare like DottedVersion, whatever follows is a
+/// pre-release tag, so "1.2-beta" < "1.2" and "1.2-beta.2" < "1.2-beta.10".
+#[derive(Debug, Clone)]
+pub struct PackageVersion {
+    /// The leading dotted numbers.
+    pub release: DottedVersion,
+    /// Whatever follows them, e.g. beta.2.
+    pub pre_release: Option<String>,
+}
+
+impl FromStr for PackageVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix(['v', 'V']).unwrap_or(s);
+
+        let mut parts = vec![];
+        let mut rest = s;
+        loop {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            if digits == 0 {
+                break;
+            }
+            parts.push(rest[..digits].parse::<u32>().map_err(|_| ())?);
+            rest = &rest[digits..];
+            match rest.strip_prefix('.') {
+                Some(next) if next.starts_with(|c: char| c.is_ascii_digit()) => rest = next,
+                _ => break,
+            }
+        }
+        if parts.is_empty() {
+            return Err(());
+        }
+
+        let tag = rest.trim_start_matches(['-', '+', '.', '_', ' ']).trim();
+        Ok(PackageVersion {
+            release: DottedVersion(parts),
+            pre_release: if tag.is_empty() {
+                None
+            } else {
+                Some(tag.to_owned())
+            },
+        })
+    }
+}
+
+impl fmt::Display for PackageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.release)?;
+        if let Some(pre_release) = &self.pre_release {
+            write!(f, "-{}", pre_release)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for PackageVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.release.cmp(&other.release).then_with(|| {
+            match (&self.pre_release, &other.pre_release) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => compare_pre_release(a, b),
+            }
+        })
+    }
+}
+
+impl PartialOrd for PackageVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for PackageVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PackageVersion {}
+
+// dot separated identifiers, numbers compare as numbers and come before words
+fn compare_pre_release(a: &str, b: &str) -> Ordering {
+    let a = a.to_lowercase();
+    let b = b.to_lowercase();
+    let mut a = a.split(['.', '-', ' ']);
+    let mut b = b.split(['.', '-', ' ']);
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> PackageVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn compares_package_versions() {
+        assert_eq!(version("1.2"), version("v1.2.0"));
+        assert!(version("1.2.3") > version("1.2"));
+        assert!(version("1.10") > version("1.9"));
+        assert!(version("v1.2.3-beta") < version("1.2.3"));
+        assert!(version("1.2.3-beta.2") < version("1.2.3-beta.10"));
+        assert!(version("1.2.3-alpha") < version("1.2.3-beta"));
+        assert!(version("2.0 RC1") > version("1.9.9"));
+        assert_eq!(version("2.0 RC1").pre_release.as_deref(), Some("RC1"));
+        assert!("beta".parse::<PackageVersion>().is_err());
+    }
+}